@@ -0,0 +1,187 @@
+//! Evaluate OCR accuracy on a directory of images with ground truth.
+//!
+//! Each image (`.png`, `.jpg`, `.jpeg` or `.webp`) in the directory must have
+//! a ground truth file with the same name and one of these extensions:
+//!
+//! - `.json` - An object with a `lines` array of strings in reading order, and
+//!   optionally a `words` array of `[left, top, right, bottom]` word boxes.
+//! - `.txt` - Expected text, one line per line. Detection metrics are not
+//!   computed for images with only a text file.
+//!
+//! Usage: `cargo run --release --example eval -- [options] <dir>`
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ocrs::{
+    EvalMetrics, GroundTruth, ImageSource, OcrEngine, OcrEngineParams, DEFAULT_IOU_THRESHOLD,
+};
+use rten_imageproc::Rect;
+use serde::Deserialize;
+
+struct Args {
+    dir: PathBuf,
+    detection_model: Option<PathBuf>,
+    recognition_model: Option<PathBuf>,
+    iou_threshold: f32,
+    verbose: bool,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut dir = None;
+    let mut detection_model = None;
+    let mut recognition_model = None;
+    let mut iou_threshold = DEFAULT_IOU_THRESHOLD;
+    let mut verbose = false;
+
+    let mut parser = lexopt::Parser::from_env();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('d') | Long("detect-model") => {
+                detection_model = Some(parser.value()?.into());
+            }
+            Short('r') | Long("rec-model") => {
+                recognition_model = Some(parser.value()?.into());
+            }
+            Long("iou") => {
+                iou_threshold = parser.value()?.parse()?;
+            }
+            Short('v') | Long("verbose") => verbose = true,
+            Value(val) if dir.is_none() => dir = Some(val.into()),
+            Long("help") => {
+                println!(
+                    "Usage: eval [--detect-model <path>] [--rec-model <path>] [--iou <threshold>] [--verbose] <dir>"
+                );
+                std::process::exit(0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    Ok(Args {
+        dir: dir.ok_or("missing <dir> argument")?,
+        detection_model,
+        recognition_model,
+        iou_threshold,
+        verbose,
+    })
+}
+
+/// Load the ground truth for the image at `image_path`, if there is any.
+fn load_ground_truth(image_path: &Path) -> Result<Option<GroundTruth>, Box<dyn Error>> {
+    let json_path = image_path.with_extension("json");
+    if json_path.exists() {
+        let json = fs::read_to_string(&json_path)?;
+        let truth = ground_truth_from_json(&json)
+            .map_err(|err| format!("{}: {}", json_path.display(), err))?;
+        return Ok(Some(truth));
+    }
+
+    let txt_path = image_path.with_extension("txt");
+    if txt_path.exists() {
+        let text = fs::read_to_string(&txt_path)?;
+        return Ok(Some(GroundTruth {
+            lines: text.lines().map(|l| l.to_string()).collect(),
+            words: Vec::new(),
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Contents of a `.json` ground truth file.
+#[derive(Deserialize)]
+struct GroundTruthFile {
+    lines: Vec<String>,
+    #[serde(default)]
+    words: Vec<[f32; 4]>,
+}
+
+fn ground_truth_from_json(json: &str) -> Result<GroundTruth, serde_json::Error> {
+    let file: GroundTruthFile = serde_json::from_str(json)?;
+    let words = file
+        .words
+        .iter()
+        .map(|coords| {
+            let [left, top, right, bottom] = coords.map(|c| c.round() as i32);
+            Rect::from_tlbr(top, left, bottom, right)
+        })
+        .collect();
+    Ok(GroundTruth {
+        lines: file.lines,
+        words,
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+
+    let mut params = OcrEngineParams::default();
+    if let Some(path) = args.detection_model {
+        params = params.with_detection_model(path);
+    }
+    if let Some(path) = args.recognition_model {
+        params = params.with_recognition_model(path);
+    }
+    if params.detection_model_source.is_none() || params.recognition_model_source.is_none() {
+        let defaults = OcrEngineParams::default().with_default_models()?;
+        params.detection_model_source = params
+            .detection_model_source
+            .or(defaults.detection_model_source);
+        params.recognition_model_source = params
+            .recognition_model_source
+            .or(defaults.recognition_model_source);
+    }
+    let engine = OcrEngine::new(params)?;
+
+    let mut image_paths: Vec<PathBuf> = fs::read_dir(&args.dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    image_paths.retain(|path| {
+        matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("png" | "jpg" | "jpeg" | "webp")
+        )
+    });
+    image_paths.sort();
+
+    let mut total = EvalMetrics::default();
+    for path in image_paths {
+        let Some(truth) = load_ground_truth(&path)? else {
+            eprintln!("Skipping {} (no ground truth)", path.display());
+            continue;
+        };
+
+        let image = image::open(&path)?.into_rgb8();
+        let input =
+            engine.prepare_input(ImageSource::from_bytes(image.as_raw(), image.dimensions())?)?;
+        let metrics = engine.evaluate(&input, &truth, args.iou_threshold)?;
+
+        if args.verbose {
+            println!(
+                "{}: CER {:.3} WER {:.3} F1 {:.3}",
+                path.display(),
+                metrics.cer(),
+                metrics.wer(),
+                metrics.f1()
+            );
+        }
+        total.add(&metrics);
+    }
+
+    println!("Images:                 {}", total.images);
+    println!("Character error rate:   {:.4}", total.cer());
+    println!("Word error rate:        {:.4}", total.wer());
+    println!("Detection precision:    {:.4}", total.precision());
+    println!("Detection recall:       {:.4}", total.recall());
+    println!("Detection F1:           {:.4}", total.f1());
+    println!(
+        "Reading order accuracy: {:.4}",
+        total.reading_order_accuracy()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,296 @@
+//! Rendering of detection and layout analysis results, for debugging.
+
+use rten_imageproc::{draw_line, Line, LineF, Painter, Point, PointF, Rect, RotatedRect, Vec2};
+use rten_tensor::prelude::*;
+use rten_tensor::{NdTensor, NdTensorView};
+
+#[cfg(feature = "png")]
+use crate::errors::OcrError;
+use crate::preprocess::BLACK_VALUE;
+use crate::recognition::line_polygon;
+
+type Rgb = [f32; 3];
+
+const WORD_COLOR: Rgb = [0., 0.8, 0.];
+const LINE_COLOR: Rgb = [0.2, 0.4, 1.];
+const SEPARATOR_COLOR: Rgb = [1., 0.6, 0.];
+const READING_ORDER_COLOR: Rgb = [0.9, 0., 0.9];
+const HEAT_MAP_COLOR: Rgb = [1., 0., 0.];
+
+/// Length of the sides of arrow heads, in pixels.
+const ARROW_HEAD_SIZE: f32 = 8.;
+
+/// Controls which layers are drawn by
+/// [OcrEngine::render_debug](crate::OcrEngine::render_debug).
+///
+/// All layers are enabled by default.
+#[derive(Clone, Debug)]
+pub struct DebugRenderOptions {
+    /// Tint pixels red according to the probability that they are part of a
+    /// text word, as returned by
+    /// [OcrEngine::detect_text_pixels](crate::OcrEngine::detect_text_pixels).
+    pub heat_map: bool,
+
+    /// Outline word rects found by detection.
+    pub words: bool,
+
+    /// Outline the polygons of text lines.
+    pub lines: bool,
+
+    /// Outline the separators between text blocks found by layout analysis,
+    /// and draw the rulings and other separators which it used.
+    pub separators: bool,
+
+    /// Draw arrows from each line to the next in reading order.
+    pub reading_order: bool,
+
+    /// Width of outlines, in pixels.
+    pub stroke_width: u32,
+}
+
+impl Default for DebugRenderOptions {
+    fn default() -> Self {
+        DebugRenderOptions {
+            heat_map: true,
+            words: true,
+            lines: true,
+            separators: true,
+            reading_order: true,
+            stroke_width: 1,
+        }
+    }
+}
+
+/// Image produced by [OcrEngine::render_debug](crate::OcrEngine::render_debug).
+///
+/// The image has the same size as the input image, with 8-bit RGBA pixels in
+/// row-major order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl DebugImage {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Return the RGBA pixel data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consume the image and return the RGBA pixel data.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Encode the image as a PNG file.
+    #[cfg(feature = "png")]
+    pub fn encode_png(&self) -> Result<Vec<u8>, OcrError> {
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.data))
+            .map_err(|err| OcrError::EncodeFailed(err.into()))?;
+        Ok(png_data)
+    }
+}
+
+/// Detection and layout analysis results to render.
+#[derive(Default)]
+pub(crate) struct DebugLayers<'a> {
+    /// (H, W) text probability map.
+    pub text_pixels: Option<NdTensorView<'a, f32, 2>>,
+    pub words: &'a [RotatedRect],
+
+    /// Lines in reading order.
+    pub lines: &'a [Vec<RotatedRect>],
+    pub separators: &'a [Rect],
+
+    /// Rulings and other separators between lines and columns.
+    pub line_separators: &'a [LineF],
+}
+
+/// Render `layers` on top of a preprocessed greyscale (1, H, W) image.
+pub(crate) fn render_debug_image(
+    image: NdTensorView<f32, 3>,
+    layers: &DebugLayers,
+    opts: &DebugRenderOptions,
+) -> DebugImage {
+    let [_, height, width] = image.shape();
+
+    // Convert the greyscale input to RGB and darken it, so that annotations
+    // stand out.
+    let mut surface = NdTensor::from_fn([3, height, width], |[_, y, x]| {
+        (image[[0, y, x]] - BLACK_VALUE) * 0.7
+    });
+
+    if let Some(text_pixels) = layers.text_pixels.filter(|_| opts.heat_map) {
+        for y in 0..height {
+            for x in 0..width {
+                let prob = text_pixels.get([y, x]).copied().unwrap_or(0.).clamp(0., 1.);
+                for c in 0..3 {
+                    let val = &mut surface[[c, y, x]];
+                    *val += (HEAT_MAP_COLOR[c] - *val) * prob * 0.6;
+                }
+            }
+        }
+    }
+
+    let mut painter = Painter::new(surface.view_mut());
+    painter.set_stroke_width(opts.stroke_width);
+    draw_outlines(&mut painter, layers, opts);
+
+    if opts.reading_order {
+        let midpoints: Vec<PointF> = layers
+            .lines
+            .iter()
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                let n = l.len() as f32;
+                let (sum_y, sum_x) = l
+                    .iter()
+                    .map(|w| w.center())
+                    .fold((0., 0.), |(y, x), c| (y + c.y, x + c.x));
+                PointF::from_yx(sum_y / n, sum_x / n)
+            })
+            .collect();
+        for (from, to) in midpoints.iter().zip(midpoints.iter().skip(1)) {
+            draw_arrow(
+                &mut surface,
+                *from,
+                *to,
+                READING_ORDER_COLOR,
+                opts.stroke_width,
+            );
+        }
+    }
+
+    let data = surface
+        .permuted([1, 2, 0])
+        .to_tensor()
+        .data()
+        .unwrap()
+        .chunks(3)
+        .flat_map(|rgb| {
+            let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| (c.clamp(0., 1.) * 255.) as u8);
+            [r, g, b, 255]
+        })
+        .collect();
+
+    DebugImage {
+        width: width as u32,
+        height: height as u32,
+        data,
+    }
+}
+
+/// Draw the outlines of separators, words and lines.
+fn draw_outlines(painter: &mut Painter<f32>, layers: &DebugLayers, opts: &DebugRenderOptions) {
+    if opts.separators {
+        painter.set_stroke(SEPARATOR_COLOR);
+        for sep in layers.separators {
+            painter.draw_polygon(&sep.corners());
+        }
+        for sep in layers.line_separators {
+            let to_int = |p: PointF| Point::from_yx(p.y.round() as i32, p.x.round() as i32);
+            painter.draw_polygon(&[to_int(sep.start), to_int(sep.end)]);
+        }
+    }
+
+    if opts.words {
+        painter.set_stroke(WORD_COLOR);
+        for word in layers.words {
+            let corners = word
+                .corners()
+                .map(|p| Point::from_yx(p.y as i32, p.x as i32));
+            painter.draw_polygon(&corners);
+        }
+    }
+
+    if opts.lines {
+        painter.set_stroke(LINE_COLOR);
+        for line in layers.lines.iter().filter(|l| !l.is_empty()) {
+            painter.draw_polygon(&line_polygon(line));
+        }
+    }
+}
+
+/// Draw an arrow from `from` to `to` on an RGB (3, H, W) image.
+fn draw_arrow(surface: &mut NdTensor<f32, 3>, from: PointF, to: PointF, color: Rgb, width: u32) {
+    let to_int = |p: PointF| Point::from_yx(p.y.round() as i32, p.x.round() as i32);
+    let mut segments = vec![Line::from_endpoints(to_int(from), to_int(to))];
+
+    let dir = Vec2::from_yx(to.y - from.y, to.x - from.x);
+    if dir.length() > 0. {
+        let back = dir.normalized() * -ARROW_HEAD_SIZE;
+        let side = back.perpendicular() * 0.5;
+        for offset in [back + side, back - side] {
+            let head_end = PointF::from_yx(to.y + offset.y, to.x + offset.x);
+            segments.push(Line::from_endpoints(to_int(to), to_int(head_end)));
+        }
+    }
+
+    for segment in segments {
+        for (c, value) in color.into_iter().enumerate() {
+            draw_line(surface.slice_mut([c]), segment, value, width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_imageproc::{Rect, RotatedRect};
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+
+    use super::{render_debug_image, DebugLayers, DebugRenderOptions, WORD_COLOR};
+    use crate::preprocess::BLACK_VALUE;
+
+    #[test]
+    fn test_render_debug_image() {
+        let [height, width] = [40, 60];
+        let image = NdTensor::full([1, height, width], BLACK_VALUE);
+        let word = RotatedRect::from_rect(Rect::from_tlhw(10, 10, 10, 20).to_f32());
+        let words = [word];
+        let lines = [words.to_vec()];
+        let layers = DebugLayers {
+            words: &words,
+            lines: &lines,
+            ..Default::default()
+        };
+        let opts = DebugRenderOptions {
+            lines: false,
+            ..Default::default()
+        };
+
+        let rendered = render_debug_image(image.view(), &layers, &opts);
+        assert_eq!(rendered.width(), width as u32);
+        assert_eq!(rendered.height(), height as u32);
+        assert_eq!(rendered.data().len(), width * height * 4);
+
+        let pixel = |y: usize, x: usize| {
+            let offset = (y * width + x) * 4;
+            &rendered.data()[offset..offset + 4]
+        };
+        let word_color = WORD_COLOR.map(|c| (c * 255.) as u8);
+        assert_eq!(pixel(10, 15)[..3], word_color);
+        assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+
+        #[cfg(feature = "png")]
+        {
+            let png = rendered.encode_png().unwrap();
+            assert!(png.starts_with(b"\x89PNG"));
+        }
+    }
+}
@@ -0,0 +1,731 @@
+//! Decoding of image files into pages for OCR.
+//!
+//! This module is available if the `decode` feature is enabled. It supports
+//! PNG, JPEG, WebP, BMP, GIF and TIFF files. Multi-page TIFF files and
+//! animated PNG, WebP and GIF files produce one page per image or frame. EXIF
+//! and TIFF orientation metadata is applied, so that pages are upright.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{
+    AnimationDecoder, DynamicImage, ImageBuffer, ImageDecoder, ImageError, ImageFormat,
+    ImageReader, Pixel,
+};
+use rten_imageproc::Rect;
+use rten_tensor::NdTensorView;
+use thiserror::Error;
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::tags::{ByteOrder, PhotometricInterpretation, Tag};
+use tiff::{ColorType as TiffColorType, TiffError};
+
+use crate::preprocess::{DimOrder, ImageSource};
+
+pub use crate::orientation::Orientation;
+
+/// Errors that can occur when decoding an image file.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// The file could not be read.
+    #[error("failed to read image: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The file format was not recognized.
+    #[error("unrecognized image format")]
+    UnknownFormat,
+
+    /// The file is in a supported format, but uses a feature of that format
+    /// which is not supported.
+    #[error("unsupported image: {0}")]
+    Unsupported(String),
+
+    /// The file is corrupt.
+    #[error("invalid image: {0}")]
+    Invalid(String),
+}
+
+impl From<ImageError> for DecodeError {
+    fn from(err: ImageError) -> DecodeError {
+        match err {
+            ImageError::Unsupported(err) => DecodeError::Unsupported(err.to_string()),
+            ImageError::IoError(err) => DecodeError::Io(err),
+            err => DecodeError::Invalid(err.to_string()),
+        }
+    }
+}
+
+impl From<TiffError> for DecodeError {
+    fn from(err: TiffError) -> DecodeError {
+        match err {
+            TiffError::IoError(err) => DecodeError::Io(err),
+            TiffError::UnsupportedError(_) | TiffError::LimitsExceeded => {
+                DecodeError::Unsupported(err.to_string())
+            }
+            err => DecodeError::Invalid(err.to_string()),
+        }
+    }
+}
+
+fn to_image_orientation(orientation: Orientation) -> image::metadata::Orientation {
+    image::metadata::Orientation::from_exif(orientation.to_exif())
+        .expect("EXIF value should be valid")
+}
+
+fn from_image_orientation(orientation: image::metadata::Orientation) -> Orientation {
+    Orientation::from_exif(orientation.to_exif()).unwrap_or_default()
+}
+
+/// A decoded page of an image file, with 8-bit pixels in HWC order.
+///
+/// The pixels have been transformed according to the file's orientation
+/// metadata, so that the image is upright. The transform which was applied
+/// is available from [DecodedImage::orientation].
+#[derive(Debug)]
+pub struct DecodedImage {
+    width: u32,
+    height: u32,
+    channels: usize,
+    data: Vec<u8>,
+    orientation: Orientation,
+}
+
+impl DecodedImage {
+    /// Convert a decoded image to greyscale, greyscale + alpha, RGB or RGBA
+    /// with 8 bits per channel, applying `orientation` to make it upright.
+    fn from_dynamic(mut image: DynamicImage, orientation: Orientation) -> DecodedImage {
+        image.apply_orientation(to_image_orientation(orientation));
+        let (width, height) = (image.width(), image.height());
+        let (channels, data) = match image {
+            DynamicImage::ImageLuma8(image) => (1, image.into_raw()),
+            DynamicImage::ImageLumaA8(image) => (2, image.into_raw()),
+            image if image.color().has_alpha() => (4, image.into_rgba8().into_raw()),
+            image if image.color().has_color() => (3, image.into_rgb8().into_raw()),
+            image => (1, image.into_luma8().into_raw()),
+        };
+        DecodedImage {
+            width,
+            height,
+            channels,
+            data,
+            orientation,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Return the number of channels, which is 1 (greyscale), 2 (greyscale +
+    /// alpha), 3 (RGB) or 4 (RGBA).
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Return the pixel data in HWC order.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Return the transform that was applied to the stored pixels to make
+    /// the image upright.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Map a rectangle in this image to the corresponding rectangle in the
+    /// pixel grid of the file, before the orientation was applied.
+    ///
+    /// See [Orientation::to_raw_rect].
+    pub fn to_raw_rect(&self, rect: Rect) -> Rect {
+        self.orientation.to_raw_rect(rect, self.width, self.height)
+    }
+
+    /// Return an [ImageSource] for use with
+    /// [OcrEngine::prepare_input](crate::OcrEngine::prepare_input).
+    pub fn to_image_source(&self) -> ImageSource<'_> {
+        let view = NdTensorView::from_data(
+            [self.height as usize, self.width as usize, self.channels],
+            self.data.as_slice(),
+        );
+        ImageSource::from_tensor(view, DimOrder::Hwc).expect("channel count should be valid")
+    }
+}
+
+/// Decode all pages of an image file.
+///
+/// Most formats contain a single page. Multi-page TIFF files and animated
+/// PNG, WebP and GIF files contain one page per image or frame.
+pub fn decode_pages(data: &[u8]) -> Result<Vec<DecodedImage>, DecodeError> {
+    let format = image::guess_format(data).map_err(|_| DecodeError::UnknownFormat)?;
+    let reader = Cursor::new(data);
+    let pages = match format {
+        ImageFormat::Tiff => decode_tiff(reader)?,
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader)?;
+            if decoder.is_apng()? {
+                decode_frames(decoder.apng()?)?
+            } else {
+                vec![decode_still(decoder)?]
+            }
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader)?;
+            if decoder.has_animation() {
+                decode_frames(decoder)?
+            } else {
+                vec![decode_still(decoder)?]
+            }
+        }
+        ImageFormat::Gif => decode_frames(GifDecoder::new(reader)?)?,
+        ImageFormat::Jpeg | ImageFormat::Bmp => {
+            let decoder = ImageReader::with_format(reader, format).into_decoder()?;
+            vec![decode_still(decoder)?]
+        }
+        format => return Err(DecodeError::Unsupported(format!("{:?} images", format))),
+    };
+
+    Ok(pages)
+}
+
+/// Read and decode all pages of an image file.
+///
+/// See [decode_pages].
+pub fn decode_file(path: impl AsRef<Path>) -> Result<Vec<DecodedImage>, DecodeError> {
+    let data = std::fs::read(path)?;
+    decode_pages(&data)
+}
+
+/// Decode a single image and apply its orientation metadata.
+fn decode_still(mut decoder: impl ImageDecoder) -> Result<DecodedImage, DecodeError> {
+    let orientation = from_image_orientation(decoder.orientation()?);
+    let image = DynamicImage::from_decoder(decoder)?;
+    Ok(DecodedImage::from_dynamic(image, orientation))
+}
+
+/// Decode all frames of an animation.
+fn decode_frames<'a>(decoder: impl AnimationDecoder<'a>) -> Result<Vec<DecodedImage>, DecodeError> {
+    let frames = decoder.into_frames().collect_frames()?;
+    Ok(frames
+        .into_iter()
+        .map(|frame| {
+            DecodedImage::from_dynamic(
+                DynamicImage::ImageRgba8(frame.into_buffer()),
+                Orientation::Normal,
+            )
+        })
+        .collect())
+}
+
+/// Decode all pages of a TIFF file.
+///
+/// Reduced-resolution images, such as thumbnails, are skipped.
+fn decode_tiff(reader: Cursor<&[u8]>) -> Result<Vec<DecodedImage>, DecodeError> {
+    let data = *reader.get_ref();
+    let mut decoder = TiffDecoder::new(reader)?;
+    let mut pages = Vec::new();
+    for ifd_index in 0.. {
+        let subfile_type = decoder
+            .find_tag_unsigned::<u32>(Tag::NewSubfileType)?
+            .unwrap_or(0);
+        if subfile_type & 1 == 0 {
+            pages.push(decode_tiff_page(&mut decoder, data, ifd_index)?);
+        }
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image()?;
+    }
+    Ok(pages)
+}
+
+/// Decode the current page of a TIFF file.
+///
+/// `data` is the whole TIFF file and `ifd_index` is the index of the page's
+/// image file directory within it.
+fn decode_tiff_page(
+    decoder: &mut TiffDecoder<Cursor<&[u8]>>,
+    data: &[u8],
+    ifd_index: usize,
+) -> Result<DecodedImage, DecodeError> {
+    let (width, height) = decoder.dimensions()?;
+    let orientation = decoder
+        .find_tag_unsigned::<u8>(Tag::Orientation)?
+        .and_then(Orientation::from_exif)
+        .unwrap_or_default();
+
+    // The TIFF decoder does not support palette images, so they are read
+    // separately.
+    let photometric = decoder.find_tag_unsigned::<u16>(Tag::PhotometricInterpretation)?;
+    let (color_type, samples) =
+        if photometric == Some(PhotometricInterpretation::RGBPalette.to_u16()) {
+            let bits = decoder
+                .find_tag_unsigned::<u8>(Tag::BitsPerSample)?
+                .unwrap_or(1);
+            (
+                TiffColorType::Palette(bits),
+                read_tiff_palette_indices(data, decoder, ifd_index)?,
+            )
+        } else {
+            (decoder.colortype()?, decoder.read_image()?)
+        };
+
+    let image = match (color_type, samples) {
+        (TiffColorType::Gray(bits @ (1 | 2 | 4)), DecodingResult::U8(data)) => {
+            let max = (1 << bits) - 1;
+            let samples = unpack_samples(&data, width, height, bits)
+                .into_iter()
+                .map(|value| (value as u32 * 255 / max) as u8)
+                .collect();
+            DynamicImage::ImageLuma8(tiff_buffer(width, height, samples)?)
+        }
+        (TiffColorType::Gray(8), DecodingResult::U8(data)) => {
+            DynamicImage::ImageLuma8(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::Gray(16), DecodingResult::U16(data)) => {
+            DynamicImage::ImageLuma16(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::GrayA(8), DecodingResult::U8(data)) => {
+            DynamicImage::ImageLumaA8(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::GrayA(16), DecodingResult::U16(data)) => {
+            DynamicImage::ImageLumaA16(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::RGB(8), DecodingResult::U8(data)) => {
+            DynamicImage::ImageRgb8(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::RGB(16), DecodingResult::U16(data)) => {
+            DynamicImage::ImageRgb16(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::RGBA(8), DecodingResult::U8(data)) => {
+            DynamicImage::ImageRgba8(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::RGBA(16), DecodingResult::U16(data)) => {
+            DynamicImage::ImageRgba16(tiff_buffer(width, height, data)?)
+        }
+        (TiffColorType::Palette(bits @ (1 | 2 | 4 | 8)), DecodingResult::U8(data)) => {
+            // The color map holds all red values, then all green values,
+            // then all blue values, with 16 bits per value.
+            let color_map = decoder
+                .find_tag_unsigned_vec::<u16>(Tag::ColorMap)?
+                .ok_or_else(|| DecodeError::Invalid("missing TIFF color map".to_string()))?;
+            let n_colors = 1 << bits;
+            if color_map.len() != n_colors * 3 {
+                return Err(DecodeError::Invalid("invalid TIFF color map".to_string()));
+            }
+            let rgb = unpack_samples(&data, width, height, bits)
+                .into_iter()
+                .flat_map(|index| {
+                    let index = index as usize;
+                    [0, 1, 2].map(|c| (color_map[c * n_colors + index] >> 8) as u8)
+                })
+                .collect();
+            DynamicImage::ImageRgb8(tiff_buffer(width, height, rgb)?)
+        }
+        (TiffColorType::CMYK(8), DecodingResult::U8(data)) => {
+            let rgb = data
+                .chunks_exact(4)
+                .flat_map(|cmyk| {
+                    let k = 255 - cmyk[3] as u32;
+                    [0, 1, 2].map(|c| ((255 - cmyk[c] as u32) * k / 255) as u8)
+                })
+                .collect();
+            DynamicImage::ImageRgb8(tiff_buffer(width, height, rgb)?)
+        }
+        (color_type, _) => {
+            return Err(DecodeError::Unsupported(format!(
+                "TIFF color type {:?}",
+                color_type
+            )))
+        }
+    };
+
+    Ok(DecodedImage::from_dynamic(image, orientation))
+}
+
+/// Read the color indices of the current page of a palette-color TIFF file.
+///
+/// The TIFF decoder rejects palette images, but their samples are laid out
+/// in the same way as greyscale images. The indices are therefore read from
+/// a copy of the file in which the page's photometric interpretation is
+/// changed to greyscale.
+fn read_tiff_palette_indices(
+    data: &[u8],
+    decoder: &mut TiffDecoder<Cursor<&[u8]>>,
+    ifd_index: usize,
+) -> Result<DecodingResult, DecodeError> {
+    let invalid = || DecodeError::Invalid("invalid TIFF directory".to_string());
+    let ifd_offset = decoder.ifd_pointer().ok_or_else(invalid)?.0;
+    let ifd_offset = usize::try_from(ifd_offset).map_err(|_| invalid())?;
+    let byte_order = decoder.byte_order();
+
+    // Read an unsigned integer of `len` bytes at `offset`.
+    let read_uint = |offset: usize, len: usize| -> Result<u64, DecodeError> {
+        let bytes = data.get(offset..offset + len).ok_or_else(invalid)?;
+        let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+        Ok(match byte_order {
+            ByteOrder::LittleEndian => bytes.iter().rev().fold(0, fold),
+            ByteOrder::BigEndian => bytes.iter().fold(0, fold),
+        })
+    };
+
+    // Classic TIFF directories have a 2-byte entry count and 12-byte
+    // entries. BigTIFF directories have an 8-byte count and 20-byte entries.
+    // In both, an entry's value follows its 2-byte tag, 2-byte type and
+    // count.
+    let big_tiff = read_uint(2, 2)? == 43;
+    let (count_len, entry_len, value_offset) = if big_tiff { (8, 20, 12) } else { (2, 12, 8) };
+    let n_entries = read_uint(ifd_offset, count_len)?;
+    let mut photometric_value = None;
+    for i in 0..n_entries as usize {
+        let entry = ifd_offset + count_len + i * entry_len;
+        let is_short = read_uint(entry + 2, 2)? == 3;
+        if read_uint(entry, 2)? == Tag::PhotometricInterpretation.to_u16() as u64 && is_short {
+            photometric_value = Some(entry + value_offset);
+            break;
+        }
+    }
+    let value = photometric_value.ok_or_else(invalid)?;
+
+    let grey = PhotometricInterpretation::BlackIsZero.to_u16();
+    let mut patched = data.to_vec();
+    patched[value..value + 2].copy_from_slice(&match byte_order {
+        ByteOrder::LittleEndian => grey.to_le_bytes(),
+        ByteOrder::BigEndian => grey.to_be_bytes(),
+    });
+
+    let mut grey_decoder = TiffDecoder::new(Cursor::new(&patched[..]))?;
+    grey_decoder.seek_to_image(ifd_index)?;
+    Ok(grey_decoder.read_image()?)
+}
+
+/// Create an image buffer from the decoded samples of a TIFF page.
+fn tiff_buffer<P: Pixel>(
+    width: u32,
+    height: u32,
+    data: Vec<P::Subpixel>,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, DecodeError> {
+    ImageBuffer::from_raw(width, height, data)
+        .ok_or_else(|| DecodeError::Invalid("TIFF pixel data has wrong size".to_string()))
+}
+
+/// Unpack samples with up to 8 bits from rows which are padded to a whole
+/// number of bytes.
+fn unpack_samples(data: &[u8], width: u32, height: u32, bits: u8) -> Vec<u8> {
+    let width = width as usize;
+    let row_bytes = (width * bits as usize).div_ceil(8);
+    let per_byte = 8 / bits as usize;
+    let mask = ((1u16 << bits) - 1) as u8;
+    data.chunks(row_bytes)
+        .take(height as usize)
+        .flat_map(|row| {
+            (0..width).map(move |x| {
+                let byte = row.get(x / per_byte).copied().unwrap_or(0);
+                let shift = 8 - bits as usize * (x % per_byte + 1);
+                (byte >> shift) & mask
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+    use rten_imageproc::Rect;
+
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, RgbaImage};
+    use tiff::encoder::{colortype, TiffEncoder};
+    use tiff::tags::{PhotometricInterpretation, SampleFormat, Tag};
+
+    use super::{decode_pages, to_image_orientation, DecodeError, Orientation};
+
+    fn encode(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    /// Insert an EXIF segment with an orientation tag after the start of a
+    /// JPEG file.
+    fn add_exif_orientation(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+        let mut exif = b"Exif\0\0MM\0*\0\0\0\x08\0\x01".to_vec();
+        exif.extend([0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        exif.extend(orientation.to_be_bytes());
+        exif.extend([0, 0, 0, 0, 0, 0]);
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend([0xff, 0xe1]);
+        data.extend(((exif.len() + 2) as u16).to_be_bytes());
+        data.extend(exif);
+        data.extend(&jpeg[2..]);
+        data
+    }
+
+    #[test]
+    fn test_decode_pages() {
+        let rgb = DynamicImage::ImageRgb8(RgbImage::from_fn(5, 3, |x, y| {
+            image::Rgb([x as u8 * 10, y as u8 * 10, 0])
+        }));
+        for format in [ImageFormat::Png, ImageFormat::Bmp] {
+            let pages = decode_pages(&encode(&rgb, format)).unwrap();
+            assert_eq!(pages.len(), 1);
+            assert_eq!((pages[0].width(), pages[0].height()), (5, 3));
+            assert_eq!(pages[0].channels(), 3);
+            assert_eq!(pages[0].data(), rgb.as_bytes());
+        }
+
+        // JPEG with EXIF orientation "rotate 90 degrees clockwise". The left
+        // half of the image is black, so the top half should be after
+        // rotation.
+        let grey = DynamicImage::ImageLuma8(GrayImage::from_fn(32, 16, |x, _| {
+            image::Luma([if x < 16 { 0 } else { 255 }])
+        }));
+        let jpeg = add_exif_orientation(&encode(&grey, ImageFormat::Jpeg), 6);
+        let pages = decode_pages(&jpeg).unwrap();
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+        assert_eq!((page.width(), page.height()), (16, 32));
+        assert!(page.data()[4 * 16 + 8] < 64);
+        assert!(page.data()[28 * 16 + 8] > 192);
+        assert_eq!(page.orientation(), Orientation::Rotate90);
+
+        // The top half of the upright image is the left half of the stored
+        // image.
+        assert_eq!(
+            page.to_raw_rect(Rect::from_tlhw(0, 0, 16, 16)),
+            Rect::from_tlhw(0, 0, 16, 16)
+        );
+        assert_eq!(
+            page.to_raw_rect(Rect::from_tlhw(2, 4, 8, 10)),
+            Rect::from_tlhw(2, 2, 10, 8)
+        );
+
+        let source = page.to_image_source();
+        assert_eq!(source.shape(), [1, 32, 16]);
+
+        assert!(matches!(
+            decode_pages(b"not an image"),
+            Err(DecodeError::UnknownFormat)
+        ));
+    }
+
+    /// Encode a single-page, little-endian TIFF file whose pixel data is
+    /// already compressed.
+    fn encode_tiff_strip(
+        width: u32,
+        height: u32,
+        compression: u16,
+        photometric: u16,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let entries: [(u16, u32); 8] = [
+            (256, width),
+            (257, height),
+            (258, 1),
+            (259, compression as u32),
+            (262, photometric as u32),
+            (273, 8 + 2 + 8 * 12 + 4),
+            (278, height),
+            (279, data.len() as u32),
+        ];
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        out.extend((entries.len() as u16).to_le_bytes());
+        for (tag, value) in entries {
+            out.extend(tag.to_le_bytes());
+            out.extend(4u16.to_le_bytes());
+            out.extend(1u32.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+        out.extend(0u32.to_le_bytes());
+        out.extend(data);
+        out
+    }
+
+    #[test]
+    fn test_decode_pages_tiff() {
+        let grey: Vec<u8> = (0..6).map(|i| i * 40).collect();
+        let rgb: Vec<u8> = (0..2 * 2 * 3).map(|i| i * 20).collect();
+
+        let mut data = Vec::new();
+        let mut encoder = TiffEncoder::new(Cursor::new(&mut data)).unwrap();
+        encoder
+            .write_image::<colortype::Gray8>(3, 2, &grey)
+            .unwrap();
+
+        // Reduced-resolution image, which should be skipped.
+        let mut thumbnail = encoder.new_image::<colortype::Gray8>(1, 1).unwrap();
+        thumbnail
+            .encoder()
+            .write_tag(Tag::NewSubfileType, 1u32)
+            .unwrap();
+        thumbnail.write_data(&[0]).unwrap();
+
+        // RGB page, rotated 90 degrees.
+        let mut page = encoder.new_image::<colortype::RGB8>(2, 2).unwrap();
+        page.encoder().write_tag(Tag::Orientation, 6u16).unwrap();
+        page.write_data(&rgb).unwrap();
+
+        let pages = decode_pages(&data).unwrap();
+        assert_eq!(pages.len(), 2);
+
+        assert_eq!((pages[0].width(), pages[0].height()), (3, 2));
+        assert_eq!(pages[0].channels(), 1);
+        assert_eq!(pages[0].data(), grey);
+        assert_eq!(pages[0].orientation(), Orientation::Normal);
+
+        assert_eq!((pages[1].width(), pages[1].height()), (2, 2));
+        assert_eq!(pages[1].channels(), 3);
+        assert_eq!(pages[1].orientation(), Orientation::Rotate90);
+        let mut rotated = DynamicImage::ImageRgb8(RgbImage::from_raw(2, 2, rgb).unwrap());
+        rotated.apply_orientation(image::metadata::Orientation::Rotate90);
+        assert_eq!(pages[1].data(), rotated.as_bytes());
+    }
+
+    #[test]
+    fn test_decode_pages_tiff_palette() {
+        /// 8-bit palette color type, which the TIFF encoder does not provide.
+        struct Palette8;
+
+        impl colortype::ColorType for Palette8 {
+            type Inner = u8;
+            const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGBPalette;
+            const BITS_PER_SAMPLE: &'static [u16] = &[8];
+            const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint];
+
+            fn horizontal_predict(row: &[u8], result: &mut Vec<u8>) {
+                result.extend_from_slice(row);
+            }
+        }
+
+        // Entry `i` of the palette has red `i`, green `255 - i` and blue 0.
+        let mut color_map = vec![0u16; 256 * 3];
+        for i in 0..256 {
+            color_map[i] = (i as u16) << 8;
+            color_map[256 + i] = (255 - i as u16) << 8;
+        }
+        let indices = [0u8, 1, 128, 255];
+
+        let mut data = Vec::new();
+        let mut encoder = TiffEncoder::new(Cursor::new(&mut data)).unwrap();
+        encoder
+            .write_image::<colortype::Gray8>(1, 1, &[255])
+            .unwrap();
+        let mut page = encoder.new_image::<Palette8>(2, 2).unwrap();
+        page.encoder()
+            .write_tag(Tag::ColorMap, &color_map[..])
+            .unwrap();
+        page.write_data(&indices).unwrap();
+
+        // The palette page follows a greyscale page.
+        let pages = decode_pages(&data).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].channels(), 3);
+        let expected: Vec<u8> = indices.iter().flat_map(|&i| [i, 255 - i, 0]).collect();
+        assert_eq!(pages[1].data(), expected);
+    }
+
+    #[test]
+    fn test_decode_pages_tiff_fax() {
+        // 8x4 bilevel image with CCITT Group 4 compression. The first two
+        // rows are black in columns 0..3, the last two in columns 5..8.
+        let fax = [0x26, 0xb7, 0x88, 0x2e, 0x00, 0x20, 0x02];
+        let data = encode_tiff_strip(8, 4, 4, 0, &fax);
+
+        let pages = decode_pages(&data).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!((pages[0].width(), pages[0].height()), (8, 4));
+        assert_eq!(pages[0].channels(), 1);
+
+        let expected: Vec<u8> = (0..4)
+            .flat_map(|y| {
+                (0..8).map(move |x| {
+                    if (y < 2 && x < 3) || (y >= 2 && x >= 5) {
+                        0
+                    } else {
+                        255
+                    }
+                })
+            })
+            .collect();
+        assert_eq!(pages[0].data(), expected);
+    }
+
+    #[test]
+    fn test_decode_pages_gif() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            for value in [0, 255] {
+                let frame = RgbaImage::from_pixel(4, 3, image::Rgba([value, value, value, 255]));
+                encoder
+                    .encode_frame(Frame::from_parts(
+                        frame,
+                        0,
+                        0,
+                        Delay::from_numer_denom_ms(100, 1),
+                    ))
+                    .unwrap();
+            }
+        }
+
+        let pages = decode_pages(&data).unwrap();
+        assert_eq!(pages.len(), 2);
+        for (page, value) in pages.iter().zip([0, 255]) {
+            assert_eq!((page.width(), page.height()), (4, 3));
+            assert_eq!(page.channels(), 4);
+            assert!(page
+                .data()
+                .chunks(4)
+                .all(|pixel| pixel == [value, value, value, 255]));
+        }
+    }
+
+    #[test]
+    fn test_orientation_to_raw_rect() {
+        // Stored image with a single marked pixel at (x=1, y=0).
+        let (width, height) = (5, 3);
+        let raw = GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([if (x, y) == (1, 0) { 255 } else { 0 }])
+        });
+        let raw_rect = Rect::from_tlhw(0, 1, 1, 1);
+
+        for exif in 1..=8 {
+            let orientation = Orientation::from_exif(exif).unwrap();
+            assert_eq!(orientation.to_exif(), exif);
+
+            let mut upright = DynamicImage::ImageLuma8(raw.clone());
+            upright.apply_orientation(to_image_orientation(orientation));
+            let upright = upright.into_luma8();
+            assert_eq!(
+                orientation.swaps_dimensions(),
+                upright.width() != width,
+                "{:?}",
+                orientation
+            );
+
+            let (x, y, _) = upright
+                .enumerate_pixels()
+                .find(|(_, _, pixel)| pixel.0[0] == 255)
+                .unwrap();
+            let rect = Rect::from_tlhw(y as i32, x as i32, 1, 1);
+            assert_eq!(
+                orientation.to_raw_rect(rect, upright.width(), upright.height()),
+                raw_rect,
+                "{:?}",
+                orientation
+            );
+        }
+        assert_eq!(Orientation::from_exif(9), None);
+    }
+}
@@ -1,9 +1,12 @@
-use anyhow::anyhow;
 use rten::{Dimension, FloatOperators, Model, Operators, RunOptions};
-use rten_imageproc::{find_contours, min_area_rect, simplify_polygon, RetrievalMode, RotatedRect};
+use rten_imageproc::{
+    find_contours, min_area_rect, simplify_polygon, BoundingRect, PointF, RetrievalMode,
+    RotatedRect,
+};
 use rten_tensor::prelude::*;
 use rten_tensor::{NdTensor, NdTensorView, Tensor};
 
+use crate::errors::{ModelKind, OcrError};
 use crate::preprocess::BLACK_VALUE;
 
 /// Parameters that control post-processing of text detection model outputs.
@@ -19,6 +22,43 @@ pub struct TextDetectorParams {
     /// Threshold for per-pixel scores in output segmentation mask for
     /// classifying a pixel as text.
     pub text_threshold: f32,
+
+    /// Maximum length of the longer side of the image passed to models whose
+    /// input height or width is symbolic. Larger images are scaled down,
+    /// preserving their aspect ratio.
+    pub max_input_size: u32,
+
+    /// Minimum size of each symbolic dimension of the model input. Smaller
+    /// images are padded.
+    pub min_input_size: u32,
+
+    /// Symbolic dimensions of the model input are padded to a multiple of
+    /// this value. This should match the total stride of the model's
+    /// downsampling layers.
+    pub input_size_multiple: u32,
+
+    /// Additional scales at which to run detection, as factors applied to the
+    /// size of the image.
+    ///
+    /// Detection normally runs once, with the image scaled to fit the model's
+    /// input. This can lose small text in large images. At each additional
+    /// scale, the scaled image is instead divided into overlapping tiles of
+    /// the model's input size. For example a scale of `1.0` detects text at
+    /// the image's original resolution. The words found at all scales are
+    /// merged, removing duplicates.
+    ///
+    /// Each scale requires a copy of the image at that scale, and a run of the
+    /// model for each tile.
+    pub scales: Vec<f32>,
+
+    /// Words detected at different scales are merged by keeping smaller
+    /// words first, and then dropping a larger word if the fraction of its
+    /// area which is covered by smaller words exceeds this threshold.
+    ///
+    /// A box found at a coarse scale which covers several words is therefore
+    /// replaced by the individual words if a finer scale found most of them,
+    /// but kept if the finer scale missed some of them.
+    pub merge_overlap_threshold: f32,
 }
 
 impl Default for TextDetectorParams {
@@ -31,27 +71,110 @@ impl Default for TextDetectorParams {
 
             // Ideally the threshold would be 0.5 as a neutral value.
             text_threshold: 0.2,
+
+            // Similar to the area of the fixed 800x600 input of the initial
+            // model release, while allowing for the aspect ratio of the image.
+            max_input_size: 1024,
+            min_input_size: 64,
+            input_size_multiple: 32,
+
+            scales: Vec::new(),
+            merge_overlap_threshold: 0.5,
+        }
+    }
+}
+
+/// Distance to expand bounding boxes of connected components by.
+///
+/// This is useful when the model is trained to assign a positive label to
+/// pixels in a smaller area than the ground truth, which may be done to create
+/// separation between adjacent objects.
+const EXPAND_DIST: f32 = 3.;
+
+/// Fraction of the tile size by which adjacent tiles overlap, when detecting
+/// text in tiles of a scaled image.
+const TILE_OVERLAP: f32 = 0.25;
+
+/// Location of a text probability mask within an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct MaskPlacement {
+    /// `(y, x)` size of a mask pixel in image pixels.
+    scale: (f32, f32),
+
+    /// `(y, x)` position of the mask's top-left corner in the image.
+    offset: (f32, f32),
+
+    /// Whether the top, left, bottom and right edges of the mask lie inside
+    /// the image. Components which touch these edges are cut off by them, and
+    /// are discarded.
+    interior_edges: [bool; 4],
+}
+
+impl MaskPlacement {
+    /// Return the placement of a mask which covers the whole image.
+    fn whole_image(scale: (f32, f32)) -> MaskPlacement {
+        MaskPlacement {
+            scale,
+            offset: (0., 0.),
+            interior_edges: [false; 4],
         }
     }
 }
 
 /// Find the minimum-area oriented rectangles containing each connected
 /// component in the binary mask `mask`.
+///
+/// `placement` specifies how mask coordinates map to image coordinates. This
+/// allows components to be found in a mask that has lower resolution than the
+/// image, or covers only part of it. `expand_dist` and `min_area` are in image
+/// coordinates.
 fn find_connected_component_rects(
     mask: NdTensorView<bool, 2>,
     expand_dist: f32,
     min_area: f32,
+    placement: MaskPlacement,
 ) -> Vec<RotatedRect> {
+    let (scale_y, scale_x) = placement.scale;
+    let (offset_y, offset_x) = placement.offset;
+    let [mask_height, mask_width] = mask.shape();
+    let [top_edge, left_edge, bottom_edge, right_edge] = placement.interior_edges;
+
     find_contours(mask, RetrievalMode::External)
         .iter()
+        .filter(|poly| {
+            // Discard components which are cut off by an interior edge.
+            !poly.iter().any(|p| {
+                (top_edge && p.y == 0)
+                    || (left_edge && p.x == 0)
+                    || (bottom_edge && p.y as usize + 1 == mask_height)
+                    || (right_edge && p.x as usize + 1 == mask_width)
+            })
+        })
         .filter_map(|poly| {
-            let float_points: Vec<_> = poly.iter().map(|p| p.to_f32()).collect();
+            // Map the centers of mask pixels to the centers of the
+            // corresponding areas of the image.
+            let float_points: Vec<_> = poly
+                .iter()
+                .map(|p| {
+                    PointF::from_yx(
+                        (p.y as f32 + 0.5) * scale_y + offset_y - 0.5,
+                        (p.x as f32 + 0.5) * scale_x + offset_x - 0.5,
+                    )
+                })
+                .collect();
             let simplified = simplify_polygon(&float_points, 2. /* epsilon */);
 
             min_area_rect(&simplified).map(|mut rect| {
+                // Each mask pixel covers `scale` image pixels, so extend the
+                // rect to cover the whole area of the pixels on its boundary.
+                let up = rect.up_axis();
+                let (up_x, up_y) = (up.x.abs(), up.y.abs());
+                let pad_height = up_x * scale_x + up_y * scale_y - 1.;
+                let pad_width = up_y * scale_x + up_x * scale_y - 1.;
+
                 rect.resize(
-                    rect.width() + 2. * expand_dist,
-                    rect.height() + 2. * expand_dist,
+                    rect.width() + pad_width + 2. * expand_dist,
+                    rect.height() + pad_height + 2. * expand_dist,
                 );
                 rect
             })
@@ -60,6 +183,124 @@ fn find_connected_component_rects(
         .collect()
 }
 
+/// Size of one spatial dimension of the text detection model's input.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct InputDim {
+    /// Size of the model input.
+    input: usize,
+
+    /// Size of the part of the input occupied by the image. The rest is
+    /// padding.
+    used: usize,
+}
+
+/// Return the start offsets of tiles of size `tile_size` which cover a
+/// dimension of size `size`, with adjacent tiles overlapping by
+/// [TILE_OVERLAP].
+fn tile_offsets(size: usize, tile_size: usize) -> Vec<usize> {
+    if size <= tile_size {
+        return vec![0];
+    }
+    let overlap = (tile_size as f32 * TILE_OVERLAP) as usize;
+    let step = (tile_size - overlap).max(1);
+    let last = size - tile_size;
+    let mut offsets: Vec<usize> = (0..last).step_by(step).collect();
+    offsets.push(last);
+    offsets
+}
+
+/// Return the area of a polygon.
+fn polygon_area(points: &[PointF]) -> f32 {
+    let n = points.len();
+    let twice_area: f32 = (0..n)
+        .map(|i| {
+            let (p, q) = (points[i], points[(i + 1) % n]);
+            p.x * q.y - q.x * p.y
+        })
+        .sum();
+    twice_area.abs() / 2.
+}
+
+/// Return the intersection of two convex polygons, using the
+/// Sutherland-Hodgman algorithm.
+fn convex_intersection(subject: &[PointF], clip: &[PointF]) -> Vec<PointF> {
+    // Sign of the clip polygon's winding order, so that "inside" can be
+    // determined from the sign of a cross product.
+    let n = clip.len();
+    let winding: f32 = (0..n)
+        .map(|i| {
+            let (p, q) = (clip[i], clip[(i + 1) % n]);
+            p.x * q.y - q.x * p.y
+        })
+        .sum::<f32>()
+        .signum();
+    let side = |a: PointF, b: PointF, p: PointF| {
+        winding * ((b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x))
+    };
+
+    let mut output = subject.to_vec();
+    for i in 0..n {
+        if output.is_empty() {
+            break;
+        }
+        let (a, b) = (clip[i], clip[(i + 1) % n]);
+        let input = std::mem::take(&mut output);
+        for j in 0..input.len() {
+            let (p, q) = (input[j], input[(j + 1) % input.len()]);
+            let (side_p, side_q) = (side(a, b, p), side(a, b, q));
+            if side_p >= 0. {
+                output.push(p);
+            }
+            if (side_p >= 0.) != (side_q >= 0.) {
+                let t = side_p / (side_p - side_q);
+                output.push(PointF::from_yx(
+                    p.y + t * (q.y - p.y),
+                    p.x + t * (q.x - p.x),
+                ));
+            }
+        }
+    }
+    output
+}
+
+/// Return the area of the intersection of two oriented rectangles.
+fn rotated_rect_intersection(a: &RotatedRect, b: &RotatedRect) -> f32 {
+    if !a.bounding_rect().intersects(b.bounding_rect()) {
+        return 0.;
+    }
+    polygon_area(&convex_intersection(&a.corners(), &b.corners()))
+}
+
+/// Remove duplicate rectangles using non-maximum suppression.
+///
+/// Rectangles are visited in order of increasing area. A rectangle is kept
+/// unless the rectangles that have already been kept cover more than
+/// `overlap_threshold` of its area. This drops near-duplicates of smaller
+/// rectangles, and large rectangles around several words if most of the
+/// words were found individually. A large rectangle is kept if only some of
+/// its words were found, so that the others are not lost.
+///
+/// The covered area is the sum of the intersections with kept rectangles,
+/// which overestimates it if the kept rectangles overlap each other.
+fn non_max_suppression(mut rects: Vec<RotatedRect>, overlap_threshold: f32) -> Vec<RotatedRect> {
+    rects.sort_by(|a, b| a.area().total_cmp(&b.area()));
+    let mut kept: Vec<RotatedRect> = Vec::new();
+    for rect in rects {
+        let area = rect.area();
+        if area <= 0. {
+            continue;
+        }
+        let covered: f32 = kept
+            .iter()
+            .map(|k| rotated_rect_intersection(k, &rect))
+            .sum();
+        if covered / area <= overlap_threshold {
+            kept.push(rect);
+        }
+    }
+    kept
+}
+
 /// Text detector which finds the oriented bounding boxes of words in an input
 /// image.
 pub struct TextDetector {
@@ -72,16 +313,33 @@ impl TextDetector {
     /// Initializate a DetectionModel from a trained RTen model.
     ///
     /// This will fail if the model doesn't have the expected inputs or outputs.
-    pub fn from_model(model: Model, params: TextDetectorParams) -> anyhow::Result<TextDetector> {
+    pub fn from_model(model: Model, params: TextDetectorParams) -> Result<TextDetector, OcrError> {
+        let invalid_model = |msg: &str| OcrError::InvalidModel(ModelKind::Detection, msg.into());
+
+        if params
+            .scales
+            .iter()
+            .any(|scale| !scale.is_finite() || *scale <= 0.)
+        {
+            return Err(OcrError::InvalidConfig(
+                "detection scales must be finite and greater than zero".into(),
+            ));
+        }
+        if params.input_size_multiple == 0 || params.min_input_size > params.max_input_size {
+            return Err(OcrError::InvalidConfig(format!(
+                "detection input size multiple must be greater than zero, and min input size ({}) must not exceed max input size ({})",
+                params.min_input_size, params.max_input_size
+            )));
+        }
         let input_id = model
             .input_ids()
             .first()
             .copied()
-            .ok_or(anyhow!("model has no inputs"))?;
+            .ok_or_else(|| invalid_model("model has no inputs"))?;
         let input_shape = model
             .node_info(input_id)
             .and_then(|info| info.shape())
-            .ok_or(anyhow!("model does not specify expected input shape"))?;
+            .ok_or_else(|| invalid_model("model does not specify expected input shape"))?;
 
         Ok(TextDetector {
             model,
@@ -90,6 +348,40 @@ impl TextDetector {
         })
     }
 
+    /// Choose the size of the model input for an image of a given `[height,
+    /// width]`, given the model's input dimensions.
+    ///
+    /// Fixed dimensions are used as-is. The image is scaled down to fit if it
+    /// is larger, and padded otherwise. For symbolic dimensions the image is
+    /// scaled down, preserving its aspect ratio, only if its longer side
+    /// exceeds [TextDetectorParams::max_input_size]. The input is then padded
+    /// to a multiple of [TextDetectorParams::input_size_multiple].
+    fn input_size(&self, model_dims: [&Dimension; 2], image_size: [usize; 2]) -> [InputDim; 2] {
+        let max_size = self.params.max_input_size as f32;
+        let scale = (max_size / image_size[0].max(image_size[1]) as f32).min(1.);
+        let multiple = self.params.input_size_multiple as usize;
+        let min_size = (self.params.min_input_size as usize).next_multiple_of(multiple);
+
+        let input_dim = |dim: &Dimension, image_size: usize| match dim {
+            Dimension::Fixed(size) => InputDim {
+                input: *size,
+                used: image_size.min(*size),
+            },
+            Dimension::Symbolic(_) => {
+                let used = ((image_size as f32 * scale).round() as usize).max(1);
+                InputDim {
+                    input: used.next_multiple_of(multiple).max(min_size),
+                    used,
+                }
+            }
+        };
+
+        [
+            input_dim(model_dims[0], image_size[0]),
+            input_dim(model_dims[1], image_size[1]),
+        ]
+    }
+
     /// Return the confidence threshold used to determine whether a pixel is
     /// text or not.
     pub fn threshold(&self) -> f32 {
@@ -110,20 +402,126 @@ impl TextDetector {
         &self,
         image: NdTensorView<f32, 3>,
         debug: bool,
-    ) -> anyhow::Result<Vec<RotatedRect>> {
-        let text_mask = self.detect_text_pixels(image, debug)?;
+    ) -> Result<Vec<RotatedRect>, OcrError> {
+        let [_, img_height, img_width] = image.shape();
+
+        // Find words in the mask at the model's resolution, to avoid creating
+        // a full-resolution copy of the mask for large images.
+        let text_mask = self.detect_text_pixels_at_model_res(image, debug)?;
+        let [mask_height, mask_width] = text_mask.shape();
+        let scale = (
+            img_height as f32 / mask_height as f32,
+            img_width as f32 / mask_width as f32,
+        );
         let binary_mask = text_mask.map(|prob| *prob > self.params.text_threshold);
 
-        // Distance to expand bounding boxes by. This is useful when the model is
-        // trained to assign a positive label to pixels in a smaller area than the
-        // ground truth, which may be done to create separation between adjacent
-        // objects.
-        let expand_dist = 3.;
+        let mut words = find_connected_component_rects(
+            binary_mask.view(),
+            EXPAND_DIST,
+            self.params.min_area,
+            MaskPlacement::whole_image(scale),
+        );
+        if self.params.scales.is_empty() {
+            return Ok(words);
+        }
+
+        for &scale in &self.params.scales {
+            words.extend(self.detect_words_in_tiles(image, scale, debug)?);
+        }
+        Ok(non_max_suppression(
+            words,
+            self.params.merge_overlap_threshold,
+        ))
+    }
+
+    /// Detect words in an image scaled by `scale`, which is divided into
+    /// overlapping tiles of the model's input size.
+    ///
+    /// Words which are cut off by the edge of a tile are discarded. Words that
+    /// are smaller than the overlap between tiles are found in full in at
+    /// least one tile.
+    fn detect_words_in_tiles(
+        &self,
+        image: NdTensorView<f32, 3>,
+        scale: f32,
+        debug: bool,
+    ) -> Result<Vec<RotatedRect>, OcrError> {
+        let [chans, img_height, img_width] = image.shape();
+        let scaled_height = ((img_height as f32 * scale).round() as usize).max(1);
+        let scaled_width = ((img_width as f32 * scale).round() as usize).max(1);
+
+        let scaled_image = ([scaled_height, scaled_width] != [img_height, img_width])
+            .then(|| {
+                image
+                    .reshaped([1, chans, img_height, img_width])
+                    .as_dyn()
+                    .resize_image([scaled_height, scaled_width])
+            })
+            .transpose()
+            .map_err(|err| OcrError::ImageProcessingFailed(err.into()))?
+            .map(|t| t.into_shape([chans, scaled_height, scaled_width]));
+        let scaled_image = scaled_image.as_ref().map(|t| t.view()).unwrap_or(image);
+
+        // Size of the image in scaled pixels, for mapping coordinates back to
+        // the original image.
+        let (scale_y, scale_x) = (
+            img_height as f32 / scaled_height as f32,
+            img_width as f32 / scaled_width as f32,
+        );
+        let [tile_height, tile_width] = self.tile_size()?;
+
+        let mut words = Vec::new();
+        for y in tile_offsets(scaled_height, tile_height) {
+            for x in tile_offsets(scaled_width, tile_width) {
+                let y_end = (y + tile_height).min(scaled_height);
+                let x_end = (x + tile_width).min(scaled_width);
+                let tile = scaled_image
+                    .slice::<3, _>((.., y..y_end, x..x_end))
+                    .to_contiguous();
 
-        let word_rects =
-            find_connected_component_rects(binary_mask.view(), expand_dist, self.params.min_area);
+                let text_mask = self.detect_text_pixels_at_model_res(tile.view(), debug)?;
+                let [mask_height, mask_width] = text_mask.shape();
+                let binary_mask = text_mask.map(|prob| *prob > self.params.text_threshold);
+                let placement = MaskPlacement {
+                    scale: (
+                        scale_y * (y_end - y) as f32 / mask_height as f32,
+                        scale_x * (x_end - x) as f32 / mask_width as f32,
+                    ),
+                    offset: (y as f32 * scale_y, x as f32 * scale_x),
+                    interior_edges: [y > 0, x > 0, y_end < scaled_height, x_end < scaled_width],
+                };
 
-        Ok(word_rects)
+                // The model's output is relative to the scaled image, so the
+                // post-processing parameters are scaled to match.
+                words.extend(find_connected_component_rects(
+                    binary_mask.view(),
+                    EXPAND_DIST / scale,
+                    self.params.min_area / (scale * scale),
+                    placement,
+                ));
+            }
+        }
+
+        Ok(words)
+    }
+
+    /// Return the `[height, width]` of tiles used by
+    /// [detect_words_in_tiles](TextDetector::detect_words_in_tiles).
+    ///
+    /// This is the model's input size, or [TextDetectorParams::max_input_size]
+    /// for symbolic dimensions.
+    fn tile_size(&self) -> Result<[usize; 2], OcrError> {
+        let [_, _, in_height, in_width] = &self.input_shape[..] else {
+            return Err(OcrError::InvalidModel(
+                ModelKind::Detection,
+                "failed to get model dims".into(),
+            ));
+        };
+        let tile_dim = |dim: &Dimension| match dim {
+            Dimension::Fixed(size) => *size,
+            Dimension::Symbolic(_) => self.params.max_input_size as usize,
+        };
+        Ok([tile_dim(in_height), tile_dim(in_width)])
     }
 
     /// Detect text pixels in an image.
@@ -137,41 +535,77 @@ impl TextDetector {
         &self,
         image: NdTensorView<f32, 3>,
         debug: bool,
-    ) -> anyhow::Result<NdTensor<f32, 2>> {
+    ) -> Result<NdTensor<f32, 2>, OcrError> {
+        let [_, img_height, img_width] = image.shape();
+        let text_mask = self.detect_text_pixels_at_model_res(image, debug)?;
+        let [mask_height, mask_width] = text_mask.shape();
+        if [mask_height, mask_width] == [img_height, img_width] {
+            return Ok(text_mask);
+        }
+
+        // Resize probability mask to original input size.
+        let text_mask = text_mask
+            .into_shape([1, 1, mask_height, mask_width])
+            .as_dyn()
+            .resize_image([img_height, img_width])
+            .map_err(|err| OcrError::ImageProcessingFailed(err.into()))?;
+
+        // Remove batch, channel dims.
+        Ok(text_mask.into_shape([img_height, img_width]))
+    }
+
+    /// Detect text pixels in an image, at the resolution of the text
+    /// detection model's input.
+    ///
+    /// Returns a probability map covering the whole of the image, whose size
+    /// is the size that the image was scaled to before being passed to the
+    /// model. Areas corresponding to padding added to the image are removed.
+    fn detect_text_pixels_at_model_res(
+        &self,
+        image: NdTensorView<f32, 3>,
+        debug: bool,
+    ) -> Result<NdTensor<f32, 2>, OcrError> {
         let [img_chans, img_height, img_width] = image.shape();
 
         // Add batch dim
         let image = image.reshaped([1, img_chans, img_height, img_width]);
 
-        let [_, _, Dimension::Fixed(in_height), Dimension::Fixed(in_width)] = self.input_shape[..]
-        else {
-            return Err(anyhow!("failed to get model dims"));
+        let [_, _, in_height, in_width] = &self.input_shape[..] else {
+            return Err(OcrError::InvalidModel(
+                ModelKind::Detection,
+                "failed to get model dims".into(),
+            ));
         };
+        let [height, width] = self.input_size([in_height, in_width], [img_height, img_width]);
+
+        // Resize the image to the area of the model input that it will
+        // occupy.
+        let image = ([img_height, img_width] != [height.used, width.used])
+            .then(|| image.resize_image([height.used, width.used]))
+            .transpose()
+            .map_err(|err| OcrError::ImageProcessingFailed(err.into()))?
+            .map(|t| t.into_cow())
+            .unwrap_or(image.as_dyn().as_cow());
 
-        // Pad small images to the input size of the text detection model. This is
-        // needed because simply scaling small images up to a fixed size may produce
-        // very large or distorted text that is hard for detection/recognition to
-        // process.
+        // Pad the rest of the input.
+        //
+        // For models with fixed input dimensions, small images are padded
+        // rather than scaled up. This is needed because simply scaling small
+        // images up to a fixed size may produce very large or distorted text
+        // that is hard for detection/recognition to process.
         //
         // Padding images is however inefficient because it means that we are
-        // potentially feeding a lot of blank pixels into the text detection model.
-        // It would be better if text detection were able to accept variable-sized
-        // inputs, within some limits.
-        let pad_bottom = (in_height as i32 - img_height as i32).max(0);
-        let pad_right = (in_width as i32 - img_width as i32).max(0);
+        // potentially feeding a lot of blank pixels into the text detection
+        // model. Models with symbolic input dimensions avoid this.
+        let pad_bottom = (height.input - height.used) as i32;
+        let pad_right = (width.input - width.used) as i32;
         let image = (pad_bottom > 0 || pad_right > 0)
             .then(|| {
                 let pads = &[0, 0, 0, 0, 0, 0, pad_bottom, pad_right];
                 image.pad(pads.into(), BLACK_VALUE)
             })
-            .transpose()?
-            .map(|t| t.into_cow())
-            .unwrap_or(image.as_dyn().as_cow());
-
-        // Resize images to the text detection model's input size.
-        let image = (image.size(2) != in_height || image.size(3) != in_width)
-            .then(|| image.resize_image([in_height, in_width]))
-            .transpose()?
+            .transpose()
+            .map_err(|err| OcrError::ImageProcessingFailed(err.into()))?
             .map(|t| t.into_cow())
             .unwrap_or(image);
 
@@ -190,22 +624,18 @@ impl TextDetector {
                 } else {
                     None
                 },
-            )?
-            .try_into()?;
+            )
+            .map_err(|err| OcrError::ModelRunFailed(err.into()))?
+            .try_into()
+            .map_err(|_| {
+                OcrError::WrongOutputShape("expected detection output to be a float tensor".into())
+            })?;
 
-        // Resize probability mask to original input size and apply threshold to get a
-        // binary text/not-text mask.
+        // Remove the areas of the mask corresponding to padding, and the batch
+        // and channel dims.
         let text_mask = text_mask
-            .slice_copy((
-                ..,
-                ..,
-                ..(in_height - pad_bottom as usize),
-                ..(in_width - pad_right as usize),
-            ))
-            .resize_image([img_height, img_width])?;
-
-        // Remove batch, channel dims.
-        let text_mask = text_mask.into_shape([img_height, img_width]);
+            .slice_copy((.., .., ..height.used, ..width.used))
+            .into_shape([height.used, width.used]);
 
         Ok(text_mask)
     }
@@ -213,11 +643,14 @@ impl TextDetector {
 
 #[cfg(test)]
 mod tests {
-    use rten_imageproc::{fill_rect, Point};
+    use rten_imageproc::{fill_rect, Point, PointF, RotatedRect, Vec2};
     use rten_tensor::prelude::*;
     use rten_tensor::NdTensor;
 
-    use super::find_connected_component_rects;
+    use super::{
+        find_connected_component_rects, non_max_suppression, rotated_rect_intersection,
+        tile_offsets, MaskPlacement,
+    };
     use crate::test_util::gen_rect_grid;
 
     #[test]
@@ -239,7 +672,12 @@ mod tests {
         }
 
         let min_area = 100.;
-        let components = find_connected_component_rects(mask.view(), 0., min_area);
+        let components = find_connected_component_rects(
+            mask.view(),
+            0.,
+            min_area,
+            MaskPlacement::whole_image((1., 1.)),
+        );
         assert_eq!(components.len() as i32, grid_h * grid_w);
 
         for c in components.iter() {
@@ -255,4 +693,104 @@ mod tests {
             assert_eq!(shape, expected_shape);
         }
     }
+
+    #[test]
+    fn test_find_connected_component_rects_scaled() {
+        // Mask with half the resolution of the image.
+        let mut mask = NdTensor::zeros([100, 200]);
+        let rects = gen_rect_grid(
+            Point::from_yx(5, 5),
+            (2, 3),  /* grid_shape */
+            (5, 25), /* rect_size */
+            (5, 3),  /* gap_size */
+        );
+        for r in rects.iter() {
+            fill_rect(mask.view_mut(), *r, true);
+        }
+
+        let components = find_connected_component_rects(
+            mask.view(),
+            0.,
+            100.,
+            MaskPlacement::whole_image((2., 2.)),
+        );
+        assert_eq!(components.len(), rects.len());
+
+        for c in components.iter() {
+            // Each component covers 10x50 image pixels. Rect sizes are
+            // measured between the centers of the outermost pixels.
+            let mut shape = [c.height().round() as i32, c.width().round() as i32];
+            shape.sort();
+            assert_eq!(shape, [9, 49]);
+        }
+
+        // The first rect covers image pixels 10..20 and 10..60.
+        let first = components
+            .iter()
+            .min_by(|a, b| (a.center().x + a.center().y).total_cmp(&(b.center().x + b.center().y)))
+            .unwrap();
+        assert!((first.center().y - 14.5).abs() < 0.01);
+        assert!((first.center().x - 34.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_tile_offsets() {
+        assert_eq!(tile_offsets(50, 100), [0]);
+        assert_eq!(tile_offsets(100, 100), [0]);
+        assert_eq!(tile_offsets(200, 100), [0, 75, 100]);
+        assert_eq!(tile_offsets(250, 100), [0, 75, 150]);
+    }
+
+    #[test]
+    fn test_non_max_suppression() {
+        let rect = |cy: f32, cx: f32, w: f32, h: f32, angle: f32| {
+            let up = Vec2::from_yx(-angle.cos(), angle.sin());
+            RotatedRect::new(PointF::from_yx(cy, cx), up, w, h)
+        };
+
+        // Identical, disjoint and partially overlapping rects.
+        let a = rect(10., 10., 20., 10., 0.);
+        assert!((rotated_rect_intersection(&a, &a) - 200.).abs() < 1e-2);
+        assert_eq!(
+            rotated_rect_intersection(&a, &rect(100., 100., 20., 10., 0.)),
+            0.
+        );
+        let shifted = rect(10., 20., 20., 10., 0.);
+        assert!((rotated_rect_intersection(&a, &shifted) - 100.).abs() < 1e-2);
+
+        // A square rotated by 45 degrees, inside a larger square.
+        let diamond = rect(10., 10., 10., 10., std::f32::consts::FRAC_PI_4);
+        let square = rect(10., 10., 20., 20., 0.);
+        assert!((rotated_rect_intersection(&diamond, &square) - 100.).abs() < 1e-2);
+
+        let rects = vec![
+            a,
+            rect(10., 11., 20., 10., 0.),
+            rect(10., 10., 22., 11., 0.),
+            rect(50., 10., 20., 10., 0.),
+            shifted,
+        ];
+        let kept = non_max_suppression(rects, 0.5);
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[0].width(), 20.);
+        assert!(kept.iter().any(|r| r.center() == PointF::from_yx(50., 10.)));
+        assert!(kept.iter().any(|r| r.center() == PointF::from_yx(10., 20.)));
+
+        // A box found at a coarse scale which covers two words, and the boxes
+        // of the individual words found at a finer scale. Each word has a low
+        // IoU with the coarse box, but is contained in it.
+        let coarse = rect(20., 50., 80., 12., 0.);
+        let words = [rect(20., 30., 35., 10., 0.), rect(20., 70., 35., 10., 0.)];
+        let kept = non_max_suppression(vec![coarse, words[0], words[1]], 0.5);
+        assert_eq!(kept.len(), 2);
+        for word in words {
+            assert!(kept.iter().any(|r| r.center() == word.center()));
+        }
+
+        // If the finer scale only found one of the words, the coarse box is
+        // kept so that the other word is not lost.
+        let kept = non_max_suppression(vec![coarse, words[0]], 0.5);
+        assert_eq!(kept.len(), 2);
+        assert!(kept.iter().any(|r| r.center() == coarse.center()));
+    }
 }
@@ -0,0 +1,275 @@
+//! Multi-page documents, which are processed together so that information
+//! from other pages can be used when analyzing each page.
+
+use rten_imageproc::Rect;
+
+use crate::orientation::Orientation;
+use crate::roles::TextRole;
+use crate::text_items::TextLine;
+use crate::OcrInput;
+
+/// A document consisting of multiple pages, such as the images of a scanned
+/// book or a multi-page TIFF file.
+///
+/// Pages are processed in parallel by
+/// [OcrEngine::process_document](crate::OcrEngine::process_document).
+#[derive(Default)]
+pub struct OcrDocument {
+    pages: Vec<OcrInput>,
+    orientations: Vec<Orientation>,
+}
+
+impl OcrDocument {
+    /// Create an empty document.
+    pub fn new() -> OcrDocument {
+        OcrDocument::default()
+    }
+
+    /// Create a document from pages which have been prepared using
+    /// [OcrEngine::prepare_input](crate::OcrEngine::prepare_input).
+    ///
+    /// The pages are assumed to be upright images of the source.
+    pub fn from_pages(pages: Vec<OcrInput>) -> OcrDocument {
+        let orientations = vec![Orientation::Normal; pages.len()];
+        OcrDocument {
+            pages,
+            orientations,
+        }
+    }
+
+    /// Add a page to the end of the document.
+    pub fn push_page(&mut self, page: OcrInput) {
+        self.push_oriented_page(page, Orientation::Normal);
+    }
+
+    /// Add a page to the end of the document, which was made upright by
+    /// applying `orientation` to the pixels of the source image.
+    ///
+    /// The orientation is reported in [PageResult::orientation].
+    pub fn push_oriented_page(&mut self, page: OcrInput, orientation: Orientation) {
+        self.pages.push(page);
+        self.orientations.push(orientation);
+    }
+
+    /// Return the pages of the document, in order.
+    pub fn pages(&self) -> &[OcrInput] {
+        &self.pages
+    }
+
+    /// Return the transform that was applied to the source image of each
+    /// page to make it upright.
+    pub fn orientations(&self) -> &[Orientation] {
+        &self.orientations
+    }
+
+    /// Return the number of pages in the document.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Return true if the document has no pages.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+/// Text recognized in one page of an [OcrDocument].
+#[derive(Clone)]
+pub struct PageResult {
+    /// Index of the page in the document.
+    pub index: usize,
+
+    /// Text lines in reading order.
+    pub lines: Vec<Option<TextLine>>,
+
+    /// Role of each line in [PageResult::lines]. Running headers and footers
+    /// are detected using text which is repeated on other pages.
+    pub roles: Vec<TextRole>,
+
+    /// Width of the upright page image.
+    pub width: u32,
+
+    /// Height of the upright page image.
+    pub height: u32,
+
+    /// Transform that was applied to the source image to make the page
+    /// upright. Coordinates in [PageResult::lines] refer to the upright
+    /// image.
+    pub orientation: Orientation,
+}
+
+impl PageResult {
+    /// Map a rectangle in the upright page image, such as
+    /// [TextChar::rect](crate::TextChar::rect), to the corresponding
+    /// rectangle in the pixel grid of the source image.
+    pub fn to_raw_rect(&self, rect: Rect) -> Rect {
+        self.orientation.to_raw_rect(rect, self.width, self.height)
+    }
+
+    /// Return the text of lines in this page whose role matches `filter`.
+    fn line_texts(&self, filter: impl Fn(TextRole) -> bool) -> Vec<String> {
+        self.lines
+            .iter()
+            .zip(&self.roles)
+            .filter(|(_, role)| filter(**role))
+            .filter_map(|(line, _)| line.as_ref().map(|l| l.to_string()))
+            .collect()
+    }
+}
+
+/// Text recognized in an [OcrDocument].
+#[derive(Clone)]
+pub struct DocumentResult {
+    /// Results for each page, in the same order as the document's pages.
+    pub pages: Vec<PageResult>,
+}
+
+impl DocumentResult {
+    /// Return all text in the document.
+    ///
+    /// Lines are separated by newlines and pages by blank lines. A word which
+    /// is hyphenated at the end of a page is joined with its continuation at
+    /// the start of the next page.
+    pub fn text(&self) -> String {
+        self.join_pages(|_| true)
+    }
+
+    /// Return the main text of the document, excluding headers, footers,
+    /// page numbers, captions and marginalia.
+    ///
+    /// Excluding running headers and footers means that a word which is
+    /// hyphenated at the end of a page's body text can be joined with its
+    /// continuation on the next page.
+    pub fn body_text(&self) -> String {
+        self.join_pages(|role| role == TextRole::Body)
+    }
+
+    fn join_pages(&self, filter: impl Fn(TextRole) -> bool) -> String {
+        let mut pages: Vec<Vec<String>> =
+            self.pages.iter().map(|p| p.line_texts(&filter)).collect();
+
+        for i in 1..pages.len() {
+            let (prev_pages, next_pages) = pages.split_at_mut(i);
+            let Some(prev) = prev_pages.iter_mut().rev().find(|p| !p.is_empty()) else {
+                continue;
+            };
+            let next = &mut next_pages[0];
+            if let (Some(last), Some(first)) = (prev.last_mut(), next.first_mut()) {
+                if join_hyphenated(last, first) && first.is_empty() {
+                    next.remove(0);
+                }
+            }
+        }
+
+        pages
+            .iter()
+            .filter(|p| !p.is_empty())
+            .map(|p| p.join("\n"))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// If `line` ends with a word broken by a hyphen and `next_line` starts with
+/// its continuation, move the continuation to the end of `line`, replacing
+/// the hyphen. Returns true if the lines were joined.
+fn join_hyphenated(line: &mut String, next_line: &mut String) -> bool {
+    let trimmed = line.trim_end();
+    let Some(stem) = trimmed.strip_suffix('-') else {
+        return false;
+    };
+    if !stem.ends_with(|c: char| c.is_alphabetic()) {
+        return false;
+    }
+    let next = next_line.trim_start();
+    if !next.starts_with(|c: char| c.is_lowercase()) {
+        return false;
+    }
+
+    let word_end = next.find(char::is_whitespace).unwrap_or(next.len());
+    let joined = format!("{}{}", stem, &next[..word_end]);
+    let rest = next[word_end..].trim_start().to_string();
+    *line = joined;
+    *next_line = rest;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_imageproc::Rect;
+
+    use super::{DocumentResult, PageResult};
+    use crate::orientation::Orientation;
+    use crate::roles::TextRole;
+    use crate::text_items::{TextChar, TextLine};
+
+    fn gen_page(index: usize, lines: &[(&str, TextRole)]) -> PageResult {
+        PageResult {
+            index,
+            lines: lines
+                .iter()
+                .map(|(text, _)| {
+                    let chars = text
+                        .chars()
+                        .map(|char| TextChar {
+                            char,
+                            rect: Rect::from_tlhw(0, 0, 10, 10),
+                        })
+                        .collect();
+                    TextLine::try_new(chars)
+                })
+                .collect(),
+            roles: lines.iter().map(|(_, role)| *role).collect(),
+            width: 100,
+            height: 50,
+            orientation: Orientation::Normal,
+        }
+    }
+
+    #[test]
+    fn test_document_text() {
+        let doc = DocumentResult {
+            pages: vec![
+                gen_page(
+                    0,
+                    &[
+                        ("Running title", TextRole::Header),
+                        ("The quick brown fox jum-", TextRole::Body),
+                        ("1", TextRole::PageNumber),
+                    ],
+                ),
+                gen_page(
+                    1,
+                    &[
+                        ("Running title", TextRole::Header),
+                        ("ped over the lazy dog. A well-", TextRole::Body),
+                        ("2", TextRole::PageNumber),
+                    ],
+                ),
+                gen_page(2, &[("Known fact.", TextRole::Body)]),
+            ],
+        };
+
+        assert_eq!(
+            doc.body_text(),
+            "The quick brown fox jumped\n\nover the lazy dog. A well-\n\nKnown fact."
+        );
+        assert_eq!(
+            doc.text(),
+            "Running title\nThe quick brown fox jum-\n1\n\nRunning title\nped over the lazy dog. A well-\n2\n\nKnown fact."
+        );
+    }
+
+    #[test]
+    fn test_page_result_to_raw_rect() {
+        let mut page = gen_page(0, &[]);
+        let rect = Rect::from_tlhw(0, 0, 10, 20);
+        assert_eq!(page.to_raw_rect(rect), rect);
+
+        // The upright page is 100x50. The stored image is 50x100 and was
+        // rotated 90 degrees clockwise, so the top of the page is the left
+        // edge of the stored image.
+        page.orientation = Orientation::Rotate90;
+        assert_eq!(page.to_raw_rect(rect), Rect::from_tlbr(80, 0, 100, 10));
+    }
+}
@@ -1,5 +1,8 @@
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
+
+use crate::preprocess::ImageSourceError;
 
 /// The error type returned when running a machine learning model fails.
 #[derive(Debug)]
@@ -23,3 +26,157 @@ impl fmt::Display for ModelRunError {
 }
 
 impl Error for ModelRunError {}
+
+/// Identifies one of the models used by [OcrEngine](crate::OcrEngine).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModelKind {
+    /// The text detection model.
+    Detection,
+
+    /// The text recognition model.
+    Recognition,
+}
+
+impl fmt::Display for ModelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ModelKind::Detection => write!(f, "detection"),
+            ModelKind::Recognition => write!(f, "recognition"),
+        }
+    }
+}
+
+/// The error type returned by [OcrEngine](crate::OcrEngine) methods.
+///
+/// New variants may be added in future, but existing variants will not
+/// change meaning, so callers can match on them to decide how to handle a
+/// failure (eg. whether it is worth retrying).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OcrError {
+    /// A model needed by the operation was not loaded.
+    ModelMissing(ModelKind),
+
+    /// A model file or buffer could not be loaded.
+    ModelLoadFailed {
+        /// Path of the model file, if the model was loaded from a file.
+        path: Option<PathBuf>,
+
+        /// The error returned by RTen.
+        error: rten::ModelLoadError,
+    },
+
+    /// A model does not have the inputs, outputs or metadata expected for
+    /// its kind.
+    InvalidModel(ModelKind, String),
+
+    /// The engine configuration is invalid.
+    InvalidConfig(String),
+
+    /// The input image or text line regions passed to the engine are
+    /// invalid.
+    InvalidInput(String),
+
+    /// The image data passed to [ImageSource](crate::ImageSource) is invalid.
+    InvalidImage(ImageSourceError),
+
+    /// Resizing or padding an image before or after running a model failed.
+    ImageProcessingFailed(Box<dyn Error + Send + Sync>),
+
+    /// Model execution failed.
+    ModelRunFailed(Box<dyn Error + Send + Sync>),
+
+    /// The model output had a different data type or shape than expected.
+    WrongOutputShape(String),
+
+    /// The number of output classes of the recognition model does not match
+    /// the size of the alphabet.
+    AlphabetMismatch {
+        /// Number of characters in the alphabet.
+        alphabet_len: usize,
+
+        /// Number of output classes of the model, including the CTC blank
+        /// class.
+        output_classes: usize,
+    },
+
+    /// A debug image could not be encoded.
+    EncodeFailed(Box<dyn Error + Send + Sync>),
+
+    /// An image file could not be decoded.
+    ///
+    /// The source error is a `DecodeError` from the `decode` module, which is
+    /// only available when the `decode` feature is enabled.
+    Decode(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            OcrError::ModelMissing(kind) => write!(f, "{} model not loaded", kind),
+            OcrError::ModelLoadFailed {
+                path: Some(path),
+                error,
+            } => write!(f, "failed to load model \"{}\": {}", path.display(), error),
+            OcrError::ModelLoadFailed { path: None, error } => {
+                write!(f, "failed to load model: {}", error)
+            }
+            OcrError::InvalidModel(kind, err) => write!(f, "invalid {} model: {}", kind, err),
+            OcrError::InvalidConfig(err) => write!(f, "invalid configuration: {}", err),
+            OcrError::InvalidInput(err) => write!(f, "invalid input: {}", err),
+            OcrError::InvalidImage(err) => write!(f, "invalid image: {}", err),
+            OcrError::ImageProcessingFailed(err) => write!(f, "image processing failed: {}", err),
+            OcrError::ModelRunFailed(err) => write!(f, "model run failed: {}", err),
+            OcrError::WrongOutputShape(err) => {
+                write!(f, "model output had unexpected type or shape: {}", err)
+            }
+            OcrError::AlphabetMismatch {
+                alphabet_len,
+                output_classes,
+            } => write!(
+                f,
+                "recognition model output column count ({}) does not match alphabet size ({})",
+                output_classes,
+                alphabet_len + 1
+            ),
+            OcrError::EncodeFailed(err) => write!(f, "failed to encode image: {}", err),
+            OcrError::Decode(err) => write!(f, "failed to decode image: {}", err),
+        }
+    }
+}
+
+impl Error for OcrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OcrError::ModelLoadFailed { error, .. } => Some(error),
+            OcrError::InvalidImage(err) => Some(err),
+            OcrError::ImageProcessingFailed(err) => Some(err.as_ref()),
+            OcrError::ModelRunFailed(err) => Some(err.as_ref()),
+            OcrError::EncodeFailed(err) => Some(err.as_ref()),
+            OcrError::Decode(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ModelRunError> for OcrError {
+    fn from(err: ModelRunError) -> OcrError {
+        match err {
+            ModelRunError::RunFailed(err) => OcrError::ModelRunFailed(err),
+            ModelRunError::WrongOutput(err) => OcrError::WrongOutputShape(err),
+        }
+    }
+}
+
+impl From<ImageSourceError> for OcrError {
+    fn from(err: ImageSourceError) -> OcrError {
+        OcrError::InvalidImage(err)
+    }
+}
+
+#[cfg(feature = "decode")]
+impl From<crate::decode::DecodeError> for OcrError {
+    fn from(err: crate::decode::DecodeError) -> OcrError {
+        OcrError::Decode(Box::new(err))
+    }
+}
@@ -0,0 +1,331 @@
+//! Accuracy metrics for evaluating OCR results against ground truth.
+//!
+//! The metrics are:
+//!
+//! - Character error rate (CER) and word error rate (WER), computed from the
+//!   edit distance between recognized and expected text.
+//! - Detection precision, recall and F1, computed by matching detected words
+//!   to ground truth word boxes by IoU.
+//! - Reading order accuracy, the fraction of consecutive pairs of ground truth
+//!   lines which were output in the same order.
+//!
+//! Metrics are accumulated as counts in [EvalMetrics], so that results for
+//! many images can be combined before computing rates.
+
+use rten_imageproc::{BoundingRect, Rect, RotatedRect};
+
+/// Minimum IoU for a detected word to match a ground truth word.
+pub const DEFAULT_IOU_THRESHOLD: f32 = 0.5;
+
+/// Maximum normalized edit distance between a ground truth line and a
+/// recognized line, for the lines to be considered the same when measuring
+/// reading order.
+const LINE_MATCH_THRESHOLD: f32 = 0.5;
+
+/// Expected OCR output for an image.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GroundTruth {
+    /// Text lines in reading order.
+    pub lines: Vec<String>,
+
+    /// Axis-aligned boxes of words in the image. If empty, detection metrics
+    /// are not computed.
+    pub words: Vec<Rect>,
+}
+
+/// Error counts from evaluating OCR output for one or more images.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EvalMetrics {
+    /// Number of images evaluated.
+    pub images: usize,
+
+    /// Character-level edit distance between recognized and expected text.
+    pub char_errors: usize,
+
+    /// Number of characters in the expected text.
+    pub chars: usize,
+
+    /// Word-level edit distance between recognized and expected text.
+    pub word_errors: usize,
+
+    /// Number of words in the expected text.
+    pub words: usize,
+
+    /// Detected words which matched a ground truth word.
+    pub true_positives: usize,
+
+    /// Detected words which did not match a ground truth word.
+    pub false_positives: usize,
+
+    /// Ground truth words which did not match a detected word.
+    pub false_negatives: usize,
+
+    /// Consecutive pairs of ground truth lines which were recognized in the
+    /// same order.
+    pub ordered_line_pairs: usize,
+
+    /// Consecutive pairs of ground truth lines which were both recognized.
+    pub line_pairs: usize,
+}
+
+impl EvalMetrics {
+    /// Compute metrics for one image.
+    ///
+    /// `lines` are the recognized lines in reading order and `words` are the
+    /// detected words.
+    pub fn from_results(
+        lines: &[String],
+        words: &[RotatedRect],
+        truth: &GroundTruth,
+        iou_threshold: f32,
+    ) -> EvalMetrics {
+        let text = lines.join("\n");
+        let expected_text = truth.lines.join("\n");
+        let chars: Vec<char> = text.chars().collect();
+        let expected_chars: Vec<char> = expected_text.chars().collect();
+        let text_words: Vec<&str> = text.split_whitespace().collect();
+        let expected_words: Vec<&str> = expected_text.split_whitespace().collect();
+
+        let mut metrics = EvalMetrics {
+            images: 1,
+            char_errors: edit_distance(&chars, &expected_chars),
+            chars: expected_chars.len(),
+            word_errors: edit_distance(&text_words, &expected_words),
+            words: expected_words.len(),
+            ..Default::default()
+        };
+
+        if !truth.words.is_empty() {
+            let matched = match_words(words, &truth.words, iou_threshold);
+            metrics.true_positives = matched;
+            metrics.false_positives = words.len() - matched;
+            metrics.false_negatives = truth.words.len() - matched;
+        }
+
+        let (ordered, pairs) = reading_order_pairs(lines, &truth.lines);
+        metrics.ordered_line_pairs = ordered;
+        metrics.line_pairs = pairs;
+
+        metrics
+    }
+
+    /// Add the counts from `other` to this.
+    pub fn add(&mut self, other: &EvalMetrics) {
+        self.images += other.images;
+        self.char_errors += other.char_errors;
+        self.chars += other.chars;
+        self.word_errors += other.word_errors;
+        self.words += other.words;
+        self.true_positives += other.true_positives;
+        self.false_positives += other.false_positives;
+        self.false_negatives += other.false_negatives;
+        self.ordered_line_pairs += other.ordered_line_pairs;
+        self.line_pairs += other.line_pairs;
+    }
+
+    /// Character error rate.
+    pub fn cer(&self) -> f32 {
+        ratio(self.char_errors, self.chars)
+    }
+
+    /// Word error rate.
+    pub fn wer(&self) -> f32 {
+        ratio(self.word_errors, self.words)
+    }
+
+    /// Fraction of detected words which matched a ground truth word.
+    pub fn precision(&self) -> f32 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    /// Fraction of ground truth words which matched a detected word.
+    pub fn recall(&self) -> f32 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+
+    /// Harmonic mean of precision and recall.
+    pub fn f1(&self) -> f32 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0. {
+            return 0.;
+        }
+        2. * precision * recall / (precision + recall)
+    }
+
+    /// Fraction of consecutive ground truth line pairs which were recognized
+    /// in the correct order.
+    pub fn reading_order_accuracy(&self) -> f32 {
+        ratio(self.ordered_line_pairs, self.line_pairs)
+    }
+}
+
+/// Return `num / denom`, or zero if `denom` is zero.
+fn ratio(num: usize, denom: usize) -> f32 {
+    if denom == 0 {
+        0.
+    } else {
+        num as f32 / denom as f32
+    }
+}
+
+/// Return the Levenshtein distance between two sequences.
+pub fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut prev_row: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+
+    for (i, a_item) in a.iter().enumerate() {
+        row[0] = i + 1;
+        for (j, b_item) in b.iter().enumerate() {
+            let substitute_cost = prev_row[j] + usize::from(a_item != b_item);
+            row[j + 1] = substitute_cost.min(prev_row[j + 1] + 1).min(row[j] + 1);
+        }
+        std::mem::swap(&mut row, &mut prev_row);
+    }
+
+    prev_row[b.len()]
+}
+
+/// Match detected words to ground truth words and return the number of
+/// matches.
+///
+/// Pairs are matched greedily in order of decreasing IoU, using the
+/// axis-aligned bounding rects of detected words. Each word is matched at
+/// most once.
+fn match_words(words: &[RotatedRect], truth: &[Rect], iou_threshold: f32) -> usize {
+    let word_rects: Vec<Rect> = words
+        .iter()
+        .map(|w| w.bounding_rect().integral_bounding_rect())
+        .collect();
+
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for (i, word) in word_rects.iter().enumerate() {
+        for (j, truth_word) in truth.iter().enumerate() {
+            let iou = word.iou(*truth_word);
+            if iou >= iou_threshold {
+                candidates.push((iou, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut word_matched = vec![false; words.len()];
+    let mut truth_matched = vec![false; truth.len()];
+    let mut matches = 0;
+    for (_, i, j) in candidates {
+        if !word_matched[i] && !truth_matched[j] {
+            word_matched[i] = true;
+            truth_matched[j] = true;
+            matches += 1;
+        }
+    }
+    matches
+}
+
+/// Compare the order of recognized lines against the ground truth.
+///
+/// Each ground truth line is matched to the most similar recognized line.
+/// Returns `(ordered, pairs)` where `pairs` is the number of consecutive
+/// ground truth line pairs where both lines were matched, and `ordered` is
+/// the number of those pairs where the matched lines are in the same order.
+fn reading_order_pairs(lines: &[String], truth: &[String]) -> (usize, usize) {
+    let line_chars: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+
+    let matched_indices: Vec<Option<usize>> = truth
+        .iter()
+        .map(|truth_line| {
+            let truth_chars: Vec<char> = truth_line.chars().collect();
+            let max_len = |chars: &[char]| chars.len().max(truth_chars.len()).max(1);
+            line_chars
+                .iter()
+                .enumerate()
+                .map(|(i, chars)| {
+                    let dist = edit_distance(chars, &truth_chars) as f32;
+                    (i, dist / max_len(chars) as f32)
+                })
+                .filter(|(_, dist)| *dist <= LINE_MATCH_THRESHOLD)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+        })
+        .collect();
+
+    let mut ordered = 0;
+    let mut pairs = 0;
+    for pair in matched_indices.windows(2) {
+        if let [Some(a), Some(b)] = pair {
+            pairs += 1;
+            if a < b {
+                ordered += 1;
+            }
+        }
+    }
+    (ordered, pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_imageproc::{Rect, RotatedRect};
+
+    use super::{edit_distance, EvalMetrics, GroundTruth, DEFAULT_IOU_THRESHOLD};
+
+    #[test]
+    fn test_edit_distance() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars(""), &chars("")), 0);
+        assert_eq!(edit_distance(&chars("abc"), &chars("")), 3);
+        assert_eq!(edit_distance(&chars(""), &chars("abc")), 3);
+        assert_eq!(edit_distance(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(edit_distance(&chars("flaw"), &chars("lawn")), 2);
+    }
+
+    #[test]
+    fn test_eval_metrics() {
+        let truth = GroundTruth {
+            lines: ["first line", "second line", "third line"]
+                .map(String::from)
+                .to_vec(),
+            words: vec![
+                Rect::from_tlhw(0, 0, 10, 50),
+                Rect::from_tlhw(0, 60, 10, 50),
+                Rect::from_tlhw(20, 0, 10, 50),
+            ],
+        };
+
+        // Detection finds one word exactly, one approximately and one
+        // spurious word. The last two lines are swapped and contain an error.
+        let words = [
+            Rect::from_tlhw(0, 0, 10, 50),
+            Rect::from_tlhw(1, 62, 10, 48),
+            Rect::from_tlhw(100, 100, 10, 10),
+        ]
+        .map(|r| RotatedRect::from_rect(r.to_f32()));
+        let lines = ["first line", "thirb line", "second line"].map(String::from);
+
+        let metrics = EvalMetrics::from_results(&lines, &words, &truth, DEFAULT_IOU_THRESHOLD);
+
+        assert_eq!(metrics.char_errors, 11);
+        assert_eq!(metrics.words, 6);
+        assert_eq!(metrics.word_errors, 2);
+
+        assert_eq!(metrics.true_positives, 2);
+        assert_eq!(metrics.false_positives, 1);
+        assert_eq!(metrics.false_negatives, 1);
+        assert_eq!(metrics.precision(), 2. / 3.);
+        assert_eq!(metrics.recall(), 2. / 3.);
+
+        assert_eq!(metrics.line_pairs, 2);
+        assert_eq!(metrics.ordered_line_pairs, 1);
+        assert_eq!(metrics.reading_order_accuracy(), 0.5);
+
+        let mut total = EvalMetrics::default();
+        total.add(&metrics);
+        total.add(&metrics);
+        assert_eq!(total.images, 2);
+        assert_eq!(total.cer(), metrics.cer());
+    }
+}
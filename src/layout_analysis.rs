@@ -5,6 +5,48 @@ use crate::geom_util::{leftmost_edge, rightmost_edge};
 mod empty_rects;
 use empty_rects::{max_empty_rects, FilterOverlapping};
 
+pub mod ruling_lines;
+
+/// Options which adjust layout analysis for documents whose structure is
+/// known in advance.
+#[derive(Clone, Debug, Default)]
+pub struct LayoutOptions {
+    /// Additional separators which lines and paragraphs may not cross.
+    ///
+    /// Horizontal separators split paragraphs, all others split lines, in the
+    /// same way as separators found from whitespace between words.
+    pub separators: Vec<LineF>,
+
+    /// Number of columns of text on the page.
+    ///
+    /// If set, the page is split into this many columns at the widest gaps
+    /// between words, and columns are read from left to right. If there are
+    /// fewer gaps than required, all of them are used.
+    pub columns: Option<usize>,
+
+    /// Areas of the page, such as headers, footers and margins, whose words
+    /// are excluded from the output. A word is excluded if its center lies
+    /// inside one of these areas.
+    pub excluded_regions: Vec<Rect>,
+
+    /// Use horizontal and vertical lines printed on the page, such as table
+    /// rules, as separators.
+    ///
+    /// Finding these lines requires a scan of the whole image, so this is
+    /// off by default.
+    pub use_ruling_lines: bool,
+}
+
+impl LayoutOptions {
+    /// Return true if the center of `word` lies in an excluded region.
+    pub fn is_excluded(&self, word: &RotatedRect) -> bool {
+        let center = word.center();
+        self.excluded_regions
+            .iter()
+            .any(|r| r.to_f32().contains_point(center))
+    }
+}
+
 fn rects_separated_by_line(a: &RotatedRect, b: &RotatedRect, l: LineF) -> bool {
     let a_to_b = LineF::from_endpoints(a.center(), b.center());
     a_to_b.intersects(l)
@@ -146,7 +188,7 @@ pub fn find_block_separators(words: &[RotatedRect]) -> Vec<Rect> {
         &object_bboxes,
         page_rect,
         score,
-        min_width.try_into().unwrap(),
+        min_width.max(0) as u32,
         min_height,
     )
     .filter_overlapping(0.5)
@@ -154,9 +196,146 @@ pub fn find_block_separators(words: &[RotatedRect]) -> Vec<Rect> {
     .collect()
 }
 
+/// Return vertical separators which split `words` into `columns` columns.
+///
+/// Separators are placed in the middle of the widest vertical gaps between
+/// words. A gap is a range of X coordinates covered by at most a tenth of
+/// the maximum number of words covering any X coordinate, so that a few
+/// headings or other lines which span several columns do not hide the gaps.
+/// Each separator spans the vertical extent of the words which it does not
+/// cross.
+fn column_separators(words: &[RotatedRect], columns: usize) -> Vec<LineF> {
+    let Some(page_rect) = bounding_rect(words.iter()).map(|br| br.integral_bounding_rect()) else {
+        return Vec::new();
+    };
+    if columns < 2 {
+        return Vec::new();
+    }
+
+    let word_rects: Vec<Rect> = words
+        .iter()
+        .map(|w| w.bounding_rect().integral_bounding_rect())
+        .collect();
+    let left = page_rect.left();
+    let mut coverage = vec![0usize; page_rect.width().max(0) as usize];
+    for r in &word_rects {
+        for x in r.left()..r.right() {
+            coverage[(x - left) as usize] += 1;
+        }
+    }
+    let threshold = coverage.iter().max().copied().unwrap_or(0) / 10;
+
+    // Find gaps between the first and last columns which contain text.
+    let mut gaps: Vec<(i32, i32)> = Vec::new();
+    let mut gap_start = None;
+    for (i, &count) in coverage.iter().enumerate() {
+        let x = left + i as i32;
+        match (count <= threshold, gap_start) {
+            (true, None) => gap_start = Some(x),
+            (false, Some(start)) => {
+                if start > left {
+                    gaps.push((start, x));
+                }
+                gap_start = None;
+            }
+            _ => {}
+        }
+    }
+    gaps.sort_by_key(|(start, end)| std::cmp::Reverse(end - start));
+    gaps.truncate(columns - 1);
+
+    gaps.into_iter()
+        .filter_map(|(start, end)| {
+            let x = (start + end) / 2;
+            let (top, bottom) = word_rects
+                .iter()
+                .filter(|r| r.right() <= x || r.left() > x)
+                .fold(None, |extent, r| match extent {
+                    Some((top, bottom)) => Some((r.top().min(top), r.bottom().max(bottom))),
+                    None => Some((r.top(), r.bottom())),
+                })?;
+            Some(LineF::from_endpoints(
+                Point::from_yx(top, x).to_f32(),
+                Point::from_yx(bottom, x).to_f32(),
+            ))
+        })
+        .collect()
+}
+
+/// Separators found by layout analysis, which [find_text_lines] uses to
+/// group words into lines and paragraphs.
+pub(crate) struct LayoutSeparators {
+    /// Gaps between blocks of text, found from the whitespace between words.
+    pub blocks: Vec<Rect>,
+
+    /// Printed rulings, separators from [LayoutOptions::separators] and
+    /// column separators from [LayoutOptions::columns].
+    pub lines: Vec<LineF>,
+}
+
+/// Return the words which are not in any of the regions excluded by `opts`.
+fn included_words(words: &[RotatedRect], opts: &LayoutOptions) -> Vec<RotatedRect> {
+    words
+        .iter()
+        .copied()
+        .filter(|w| !opts.is_excluded(w))
+        .collect()
+}
+
+/// Find the separators which [find_text_lines] uses for the same arguments.
+pub(crate) fn find_layout_separators(
+    words: &[RotatedRect],
+    rulings: &[LineF],
+    opts: &LayoutOptions,
+) -> LayoutSeparators {
+    layout_separators(&included_words(words, opts), rulings, opts)
+}
+
+/// Find separators between `words`, which have already been filtered by
+/// [included_words].
+fn layout_separators(
+    words: &[RotatedRect],
+    rulings: &[LineF],
+    opts: &LayoutOptions,
+) -> LayoutSeparators {
+    let mut lines: Vec<LineF> = rulings
+        .iter()
+        .copied()
+        .filter(|l| !words.iter().any(|w| w.contains(l.center())))
+        .chain(opts.separators.iter().copied())
+        .collect();
+    if let Some(columns) = opts.columns {
+        lines.extend(column_separators(words, columns));
+    }
+    LayoutSeparators {
+        blocks: find_block_separators(words),
+        lines,
+    }
+}
+
 /// Group words into lines and sort them into reading order.
-pub fn find_text_lines(words: &[RotatedRect]) -> Vec<Vec<RotatedRect>> {
-    let separators = find_block_separators(words);
+///
+/// `rulings` are printed lines in the image, such as those found by
+/// [ruling_lines::find_ruling_lines]. Vertical rulings are used as column
+/// separators when grouping words into lines and horizontal rulings separate
+/// paragraphs, in addition to the separators found from whitespace between
+/// words. Rulings whose center lies inside a word are ignored, as they are
+/// likely to be strokes of the word itself.
+///
+/// `opts` supplies additional separators, a column layout and regions whose
+/// words should be excluded from the output.
+pub fn find_text_lines(
+    words: &[RotatedRect],
+    rulings: &[LineF],
+    opts: &LayoutOptions,
+) -> Vec<Vec<RotatedRect>> {
+    let words = included_words(words, opts);
+    let words = words.as_slice();
+    let LayoutSeparators {
+        blocks: separators,
+        lines: rulings,
+    } = layout_separators(words, rulings, opts);
+
     let vertical_separators: Vec<_> = separators
         .iter()
         .map(|r| {
@@ -166,6 +345,7 @@ pub fn find_text_lines(words: &[RotatedRect]) -> Vec<Vec<RotatedRect>> {
                 Point::from_yx(r.bottom(), center.x).to_f32(),
             )
         })
+        .chain(rulings.iter().copied().filter(|l| !l.is_horizontal()))
         .collect();
 
     let horizontal_separators: Vec<_> = separators
@@ -177,6 +357,7 @@ pub fn find_text_lines(words: &[RotatedRect]) -> Vec<Vec<RotatedRect>> {
                 Point::from_yx(center.y, r.right()).to_f32(),
             )
         })
+        .chain(rulings.iter().copied().filter(|l| l.is_horizontal()))
         .collect();
 
     let mut lines = group_into_lines(words, &vertical_separators);
@@ -234,10 +415,10 @@ pub fn find_text_lines(words: &[RotatedRect]) -> Vec<Vec<RotatedRect>> {
 
 #[cfg(test)]
 mod tests {
-    use rten_imageproc::{BoundingRect, Point, Rect, RectF, RotatedRect};
+    use rten_imageproc::{BoundingRect, LineF, Point, PointF, Rect, RectF, RotatedRect};
 
-    use super::{find_block_separators, find_text_lines};
-    use crate::test_util::{gen_rect_grid, union_rects};
+    use super::{find_block_separators, find_text_lines, LayoutOptions};
+    use crate::test_util::{gen_rect_grid, union_rects, PageBuilder, SyntheticPage};
 
     #[test]
     fn test_find_block_separators() {
@@ -327,7 +508,7 @@ mod tests {
 
         let mut rng = fastrand::Rng::with_seed(1234);
         rng.shuffle(&mut words);
-        let lines = find_text_lines(&words);
+        let lines = find_text_lines(&words, &[], &LayoutOptions::default());
 
         assert_eq!(lines.len() as i32, col_rows * 2);
         for line in lines {
@@ -348,4 +529,110 @@ mod tests {
             assert!((line_width - expected_width as f32).abs() <= 1.);
         }
     }
+
+    #[test]
+    fn test_find_text_lines_synthetic_pages() {
+        struct Case {
+            name: &'static str,
+            page: SyntheticPage,
+        }
+
+        let cases = [
+            Case {
+                name: "heading and paragraph",
+                page: PageBuilder::new(400, 300, 1)
+                    .paragraph(Rect::from_tlhw(20, 20, 30, 260), 24)
+                    .paragraph(Rect::from_tlhw(70, 20, 300, 260), 12)
+                    .build(),
+            },
+            Case {
+                name: "two columns",
+                page: PageBuilder::new(400, 600, 2)
+                    .columns(Rect::from_tlhw(20, 20, 360, 560), 2, 60, 12)
+                    .build(),
+            },
+            Case {
+                name: "heading and three columns",
+                page: PageBuilder::new(500, 800, 3)
+                    .paragraph(Rect::from_tlhw(20, 20, 30, 500), 24)
+                    .columns(Rect::from_tlhw(80, 20, 400, 760), 3, 60, 12)
+                    .build(),
+            },
+            Case {
+                name: "table",
+                page: PageBuilder::new(300, 600, 4)
+                    .table(Point::from_yx(20, 20), 8, 4, (30, 140), 12)
+                    .build(),
+            },
+            Case {
+                // Columns whose gap is too narrow to be found from whitespace,
+                // but which are separated by a printed rule.
+                name: "ruled columns",
+                page: PageBuilder::new(400, 600, 6)
+                    .columns(Rect::from_tlhw(20, 20, 360, 560), 2, 12, 12)
+                    .ruling(Point::from_yx(20, 300), Point::from_yx(380, 300))
+                    .build(),
+            },
+            Case {
+                name: "rotated paragraph",
+                page: PageBuilder::new(400, 400, 5)
+                    .rotated_paragraph(Rect::from_tlhw(50, 50, 200, 300), 14, 3.)
+                    .build(),
+            },
+        ];
+
+        for Case { name, page } in cases {
+            let mut words = page.words.clone();
+            fastrand::Rng::with_seed(1234).shuffle(&mut words);
+
+            let lines = page.line_indices(&find_text_lines(
+                &words,
+                &page.rulings,
+                &LayoutOptions::default(),
+            ));
+            let expected = page.line_indices(&page.expected_lines());
+            assert_eq!(lines, expected, "mismatch for page \"{}\"", name);
+        }
+    }
+
+    #[test]
+    fn test_find_text_lines_options() {
+        // Two columns with a gap which is too narrow to be found from
+        // whitespace, between a running header and a page number.
+        let page = PageBuilder::new(500, 600, 7)
+            .paragraph(Rect::from_tlhw(10, 20, 12, 200), 10)
+            .columns(Rect::from_tlhw(40, 20, 400, 560), 2, 12, 12)
+            .paragraph(Rect::from_tlhw(470, 280, 12, 40), 10)
+            .build();
+        let mut words = page.words.clone();
+        fastrand::Rng::with_seed(1234).shuffle(&mut words);
+
+        let opts = LayoutOptions {
+            columns: Some(2),
+            excluded_regions: vec![
+                Rect::from_tlhw(0, 0, 30, 600),
+                Rect::from_tlhw(460, 0, 40, 600),
+            ],
+            ..Default::default()
+        };
+        let lines = page.line_indices(&find_text_lines(&words, &[], &opts));
+
+        // The header and footer are the first and last lines on the page.
+        let mut expected = page.line_indices(&page.expected_lines());
+        expected.remove(0);
+        expected.pop();
+        assert_eq!(lines, expected);
+
+        // An explicit separator has the same effect as the column count.
+        let opts = LayoutOptions {
+            separators: vec![LineF::from_endpoints(
+                PointF::from_yx(40., 300.),
+                PointF::from_yx(440., 300.),
+            )],
+            columns: None,
+            ..opts
+        };
+        let lines = page.line_indices(&find_text_lines(&words, &[], &opts));
+        assert_eq!(lines, expected);
+    }
 }
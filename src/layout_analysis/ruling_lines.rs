@@ -0,0 +1,187 @@
+//! Detection of printed horizontal and vertical lines ("ruling lines") which
+//! separate table cells, columns and sections of a document.
+
+use rten_imageproc::{LineF, PointF};
+use rten_tensor::prelude::*;
+use rten_tensor::NdTensorView;
+
+/// Maximum thickness of a ruling line, in pixels. Thicker runs of ink are
+/// assumed to be filled regions such as images or shaded cells.
+const MAX_THICKNESS: usize = 8;
+
+/// Return the default minimum length of ruling lines for an image of a given
+/// size.
+///
+/// This is long enough that strokes of individual characters, even in large
+/// headings, are not mistaken for lines.
+pub fn default_min_length(height: usize, width: usize) -> usize {
+    (height.min(width) / 15).max(20)
+}
+
+/// A run of ink pixels along a row or column, which may be extended across
+/// adjacent rows or columns to form a thick line.
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    /// Index of the first row (or column) the segment covers.
+    first: usize,
+
+    /// Index of the last row (or column) the segment covers.
+    last: usize,
+
+    /// Start and end positions along the row (or column).
+    start: usize,
+    end: usize,
+}
+
+/// Find ruling lines in a greyscale image.
+///
+/// `image` is a (H, W) image with values in `[-0.5, 0.5]`. The polarity of
+/// ink is inferred from the mean value, so that both dark-on-light and
+/// light-on-dark documents are supported.
+///
+/// Lines are found by looking for runs of ink pixels at least `min_length`
+/// long, and merging runs on adjacent rows or columns. This is equivalent to
+/// a morphological opening with a 1D structuring element. Returned lines run
+/// along the center of the ink and are either horizontal or vertical.
+pub fn find_ruling_lines(image: NdTensorView<f32, 2>, min_length: usize) -> Vec<LineF> {
+    let [height, width] = image.shape();
+    if height == 0 || width == 0 {
+        return Vec::new();
+    }
+
+    let mean = image.iter().sum::<f32>() / (height * width) as f32;
+    let dark_ink = mean >= 0.;
+    let is_ink = |y: usize, x: usize| {
+        let value = image[[y, x]];
+        if dark_ink {
+            value < 0.
+        } else {
+            value > 0.
+        }
+    };
+
+    let horizontal = find_segments(height, width, min_length, is_ink);
+    let vertical = find_segments(width, height, min_length, |col, pos| is_ink(pos, col));
+
+    let center = |seg: &Segment| (seg.first + seg.last) as f32 / 2. + 0.5;
+    let horizontal_lines = horizontal.iter().map(|seg| {
+        let y = center(seg);
+        LineF::from_endpoints(
+            PointF::from_yx(y, seg.start as f32),
+            PointF::from_yx(y, seg.end as f32),
+        )
+    });
+    let vertical_lines = vertical.iter().map(|seg| {
+        let x = center(seg);
+        LineF::from_endpoints(
+            PointF::from_yx(seg.start as f32, x),
+            PointF::from_yx(seg.end as f32, x),
+        )
+    });
+    horizontal_lines.chain(vertical_lines).collect()
+}
+
+/// Find runs of at least `min_length` ink pixels along each of `rows` rows of
+/// length `row_len`, and merge runs in adjacent rows which overlap.
+///
+/// `is_ink(row, pos)` returns true if the pixel at a position is ink. Runs
+/// which merge into segments thicker than [MAX_THICKNESS] are discarded.
+fn find_segments<F: Fn(usize, usize) -> bool>(
+    rows: usize,
+    row_len: usize,
+    min_length: usize,
+    is_ink: F,
+) -> Vec<Segment> {
+    let min_length = min_length.max(1);
+    let mut finished = Vec::new();
+    let mut open: Vec<Segment> = Vec::new();
+
+    for row in 0..rows {
+        let mut runs = Vec::new();
+        let mut run_start = None;
+        for pos in 0..=row_len {
+            let ink = pos < row_len && is_ink(row, pos);
+            match (ink, run_start) {
+                (true, None) => run_start = Some(pos),
+                (false, Some(start)) => {
+                    if pos - start >= min_length {
+                        runs.push((start, pos));
+                    }
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+
+        let mut next_open = Vec::new();
+        for (start, end) in runs {
+            // Extend a segment from the previous row if it overlaps this run
+            // by at least half of the shorter of the two.
+            let overlapping = open.iter().position(|seg| {
+                let overlap = seg.end.min(end) as isize - seg.start.max(start) as isize;
+                overlap * 2 >= (seg.end - seg.start).min(end - start) as isize
+            });
+            let segment = match overlapping {
+                Some(idx) => {
+                    let seg = open.remove(idx);
+                    Segment {
+                        first: seg.first,
+                        last: row,
+                        start: seg.start.min(start),
+                        end: seg.end.max(end),
+                    }
+                }
+                None => Segment {
+                    first: row,
+                    last: row,
+                    start,
+                    end,
+                },
+            };
+            next_open.push(segment);
+        }
+
+        // Segments which were not extended are complete.
+        finished.append(&mut open);
+        open = next_open;
+    }
+    finished.append(&mut open);
+
+    finished.retain(|seg| seg.last - seg.first < MAX_THICKNESS);
+    finished.sort_by_key(|seg| (seg.first, seg.start));
+    finished
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_imageproc::{fill_rect, Rect};
+    use rten_tensor::prelude::*;
+    use rten_tensor::NdTensor;
+
+    use super::find_ruling_lines;
+
+    #[test]
+    fn test_find_ruling_lines() {
+        // Dark lines and text-like blobs on a light background.
+        let mut image = NdTensor::full([100, 200], 0.5);
+        fill_rect(image.view_mut(), Rect::from_tlhw(20, 10, 2, 180), -0.5);
+        fill_rect(image.view_mut(), Rect::from_tlhw(10, 100, 80, 1), -0.5);
+        fill_rect(image.view_mut(), Rect::from_tlhw(40, 20, 10, 15), -0.5);
+        // Filled region, which is too thick to be a line.
+        fill_rect(image.view_mut(), Rect::from_tlhw(60, 120, 30, 60), -0.5);
+
+        let lines = find_ruling_lines(image.view(), 30);
+
+        assert_eq!(lines.len(), 2);
+        let horizontal = lines.iter().find(|l| l.is_horizontal()).unwrap();
+        assert_eq!(horizontal.start.y, 21.);
+        assert_eq!((horizontal.start.x, horizontal.end.x), (10., 190.));
+        let vertical = lines.iter().find(|l| l.start.x == l.end.x).unwrap();
+        assert_eq!(vertical.start.x, 100.5);
+        assert_eq!((vertical.start.y, vertical.end.y), (10., 90.));
+
+        // Inverted polarity.
+        let inverted = image.map(|x| -x);
+        assert_eq!(find_ruling_lines(inverted.view(), 30).len(), 2);
+    }
+}
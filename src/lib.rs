@@ -1,37 +1,112 @@
-use anyhow::anyhow;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use rayon::prelude::*;
 use rten::Model;
-use rten_imageproc::RotatedRect;
+use rten_imageproc::{LineF, RotatedRect};
 use rten_tensor::prelude::*;
 use rten_tensor::NdTensor;
 
+mod debug_render;
+#[cfg(feature = "decode")]
+pub mod decode;
 mod detection;
+mod document;
 mod errors;
+mod eval;
 mod geom_util;
 mod layout_analysis;
 mod log;
+mod model_metadata;
+mod model_source;
+mod orientation;
 mod preprocess;
 mod recognition;
+mod roles;
+mod tables;
 
 #[cfg(test)]
 mod test_util;
 
 mod text_items;
+mod trace;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm_api;
 
-use detection::{TextDetector, TextDetectorParams};
-use layout_analysis::find_text_lines;
+use debug_render::{render_debug_image, DebugLayers};
+use detection::TextDetector;
+use layout_analysis::ruling_lines::{default_min_length, find_ruling_lines};
+use layout_analysis::{find_layout_separators, find_text_lines};
 use preprocess::prepare_image;
 use recognition::{RecognitionOpt, TextRecognizer};
-
-pub use preprocess::{DimOrder, ImagePixels, ImageSource, ImageSourceError};
-pub use recognition::DecodeMethod;
+use roles::{classify_lines, PageLines};
+use trace::{Span, StatsCollector};
+
+pub use debug_render::{DebugImage, DebugRenderOptions};
+pub use detection::TextDetectorParams;
+pub use document::{DocumentResult, OcrDocument, PageResult};
+pub use errors::{ModelKind, OcrError};
+pub use eval::{EvalMetrics, GroundTruth, DEFAULT_IOU_THRESHOLD};
+pub use layout_analysis::LayoutOptions;
+pub use model_source::{
+    default_model_dir, ModelSource, DETECTION_MODEL_FILE, MODEL_DIR_ENV_VAR, RECOGNITION_MODEL_FILE,
+};
+pub use orientation::Orientation;
+pub use preprocess::{ChannelOrder, DimOrder, ImagePixels, ImageSource, ImageSourceError};
+pub use recognition::{DecodeMethod, RecognitionParams};
+pub use roles::TextRole;
+pub use tables::{Table, TableCell, TableRow};
 pub use text_items::{TextChar, TextItem, TextLine, TextWord};
+pub use trace::{LogTraceSink, OcrStats, TraceEvent, TraceSink, TraceStage};
 
 // 注意 "E" 前面的符号应该是欧元符号。
 const DEFAULT_ALPHABET: &str = " 0123456789!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~EABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
+/// 每张输入图像中按阅读顺序排列的文本行。
+type BatchLines = Vec<Vec<Option<TextLine>>>;
+
+/// 检查调用者提供的词矩形是否可以安全地用于布局分析。
+///
+/// 矩形的角点必须是有限值，并且位于图像在每个方向上扩展其自身尺寸后的区域内。
+/// 这排除了会在整数坐标转换中溢出的矩形。
+fn validate_word_rects(input: &OcrInput, words: &[RotatedRect]) -> Result<(), OcrError> {
+    let [_, height, width] = input.image.shape();
+    let (height, width) = (height as f32, width as f32);
+
+    for word in words {
+        for corner in word.corners() {
+            let valid = corner.x.is_finite()
+                && corner.y.is_finite()
+                && (-width..=2. * width).contains(&corner.x)
+                && (-height..=2. * height).contains(&corner.y);
+            if !valid {
+                return Err(OcrError::InvalidInput(format!(
+                    "word rect corner ({}, {}) is outside the image",
+                    corner.x, corner.y
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 检查调用者提供的分隔线的端点是否都是有限值。
+fn validate_separators(separators: &[LineF]) -> Result<(), OcrError> {
+    for sep in separators {
+        let finite = [sep.start, sep.end]
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite());
+        if !finite {
+            return Err(OcrError::InvalidInput(format!(
+                "separator ({}, {}) - ({}, {}) has non-finite coordinates",
+                sep.start.x, sep.start.y, sep.end.x, sep.end.y
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// OCR 引擎实例的配置。
 #[derive(Default)]
 pub struct OcrEngineParams {
@@ -40,20 +115,65 @@ pub struct OcrEngineParams {
 
     /// 用于识别图像中文本行的模型。
     ///
-    /// 如果使用自定义模型，你可能需要调整
+    /// 如果模型元数据中嵌入了字母表（参见 [`alphabet`](Self::alphabet)），
+    /// 将自动使用它。否则，使用自定义模型时你可能需要调整
     /// [`alphabet`](Self::alphabet) 以匹配。
     pub recognition_model: Option<Model>,
 
+    /// 检测模型的来源（文件路径或字节缓冲区），在 [`OcrEngine::new`] 中加载。
+    ///
+    /// 仅在未设置 [`detection_model`](Self::detection_model) 时使用。
+    pub detection_model_source: Option<ModelSource>,
+
+    /// 识别模型的来源（文件路径或字节缓冲区），在 [`OcrEngine::new`] 中加载。
+    ///
+    /// 仅在未设置 [`recognition_model`](Self::recognition_model) 时使用。
+    pub recognition_model_source: Option<ModelSource>,
+
+    /// 从文件路径加载模型时使用内存映射而不是将文件读入内存。
+    ///
+    /// 映射的文件在引擎存活期间不得被修改。
+    ///
+    /// 需要启用 `mmap` 功能，否则 [`OcrEngine::new`] 会返回 [`OcrError::InvalidConfig`]。
+    pub memory_map: bool,
+
     /// 启用调试日志记录。
+    ///
+    /// 如果未设置 [`trace_sink`](Self::trace_sink)，各处理阶段的
+    /// [`TraceEvent`] 会通过 [`LogTraceSink`] 输出到标准输出（WebAssembly 中为浏览器控制台）。
     pub debug: bool,
 
+    /// 接收各处理阶段（预处理、检测、布局分析和识别批次）的耗时和计数事件。
+    ///
+    /// 可用于在生产环境中对 OCR 流水线进行性能分析。
+    pub trace_sink: Option<Arc<dyn TraceSink>>,
+
     /// 文本识别模型输出的解码方法。
     pub decode_method: DecodeMethod,
 
+    /// 控制文本检测输入尺寸和检测结果后处理的参数。
+    ///
+    /// 对于输入高度或宽度为符号维度的检测模型，输入尺寸会根据图像的宽高比选择：
+    /// 较大的图像会按比例缩小，使较长边不超过 [`max_input_size`](TextDetectorParams::max_input_size)，
+    /// 然后填充到 [`input_size_multiple`](TextDetectorParams::input_size_multiple) 的倍数。
+    ///
+    /// 对于同时包含很小和很大文字的文档（例如海报和包装），可以通过
+    /// [`scales`](TextDetectorParams::scales) 在多个缩放比例下分块检测，并合并去重结果。
+    pub detection_params: TextDetectorParams,
+
+    /// 控制文本行识别批次大小和最大行宽的参数。
+    ///
+    /// 在内存受限的设备上，可以减小批次大小和最大行宽以降低峰值内存使用。
+    /// 超过最大宽度的行会被拆分为相互重叠的块分别识别，然后合并结果。
+    pub recognition_params: RecognitionParams,
+
     /// 文本识别使用的字母表。
     ///
     /// 如果你使用自定义识别模型并修改了字母表，这将很有用。
-    /// 如果未指定，则会使用与 [原始模型](https://github.com/robertknight/ocrs-models) 训练时相同的默认字母表。
+    /// 如果未指定，则使用识别模型描述元数据中 `ocrs.alphabet=<字母表>` 行指定的字母表；
+    /// 如果模型也未指定，则会使用与 [原始模型](https://github.com/robertknight/ocrs-models) 训练时相同的默认字母表。
+    ///
+    /// 如果识别模型的输出形状已知，字母表会在 [`OcrEngine::new`] 中与之进行校验。
     pub alphabet: Option<String>,
 
     /// 文本识别模型可能产生的字符集。
@@ -63,6 +183,67 @@ pub struct OcrEngineParams {
     ///
     /// 如果未设置此选项，文本识别可能会生成字母表中的任何字符。
     pub allowed_chars: Option<String>,
+
+    /// 布局分析的默认选项，用于已知结构的文档类型。
+    ///
+    /// 可以指定额外的分隔线、固定的栏数，以及要排除的区域（例如页眉、页脚和页边距）。
+    /// 参见 [OcrEngine::find_text_lines_with_options]。
+    pub layout_options: LayoutOptions,
+
+    /// 从 [OcrEngine::get_text] 和 [OcrEngine::process_batch] 的输出中排除页眉、页脚、
+    /// 页码、图表标题和页边注释，只保留正文（[TextRole::Body]）。
+    /// `process_batch` 会将被排除的文本行替换为 `None`。
+    ///
+    /// [OcrEngine::process_document] 忽略此选项，因为它会返回每个文本行的
+    /// [TextRole]；请使用 [DocumentResult::body_text] 获取正文。
+    ///
+    /// 参见 [OcrEngine::classify_lines]。
+    pub body_text_only: bool,
+}
+
+impl OcrEngineParams {
+    /// 设置从路径或字节缓冲区加载的检测模型。
+    pub fn with_detection_model(mut self, source: impl Into<ModelSource>) -> Self {
+        self.detection_model_source = Some(source.into());
+        self
+    }
+
+    /// 设置从路径或字节缓冲区加载的识别模型。
+    pub fn with_recognition_model(mut self, source: impl Into<ModelSource>) -> Self {
+        self.recognition_model_source = Some(source.into());
+        self
+    }
+
+    /// 使用默认模型目录（参见 [`default_model_dir`]）中的
+    /// [`DETECTION_MODEL_FILE`] 和 [`RECOGNITION_MODEL_FILE`] 模型。
+    ///
+    /// 如果无法确定默认模型目录则失败。模型文件本身在 [`OcrEngine::new`] 中加载。
+    ///
+    /// ```no_run
+    /// use ocrs::{OcrEngine, OcrEngineParams};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let engine = OcrEngine::new(OcrEngineParams::default().with_default_models()?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_default_models(self) -> Result<Self, OcrError> {
+        let dir = default_model_dir().ok_or_else(|| {
+            OcrError::InvalidConfig(format!(
+                "could not determine the default model directory, set the {} environment variable",
+                MODEL_DIR_ENV_VAR
+            ))
+        })?;
+        Ok(self
+            .with_detection_model(dir.join(DETECTION_MODEL_FILE))
+            .with_recognition_model(dir.join(RECOGNITION_MODEL_FILE)))
+    }
+
+    /// 设置从文件路径加载模型时是否使用内存映射。参见 [`memory_map`](Self::memory_map)。
+    pub fn with_memory_map(mut self, memory_map: bool) -> Self {
+        self.memory_map = memory_map;
+        self
+    }
 }
 
 /// 检测并识别图像中的文本。
@@ -73,7 +254,9 @@ pub struct OcrEngine {
     recognizer: Option<TextRecognizer>,
     debug: bool,
     decode_method: DecodeMethod,
-    alphabet: String,
+    trace_sink: Option<Arc<dyn TraceSink>>,
+    layout_options: LayoutOptions,
+    body_text_only: bool,
 
     /// 在 `alphabet` 中被排除在识别输出之外的字符索引。
     /// 请参见 [`OcrEngineParams::allowed_chars`]。
@@ -84,23 +267,46 @@ pub struct OcrEngine {
 pub struct OcrInput {
     /// 归一化的像素值 [BLACK_VALUE, BLACK_VALUE + 1.] 的 CHW 张量。
     pub(crate) image: NdTensor<f32, 3>,
+
+    /// [OcrEngine::prepare_input] 创建此输入所用的时间，计入 [OcrStats::preprocess_time]。
+    preprocess_time: Duration,
 }
 
 impl OcrEngine {
     /// 根据给定的配置构造一个新的引擎。
-    pub fn new(params: OcrEngineParams) -> anyhow::Result<OcrEngine> {
-        let detector = params
-            .detection_model
-            .map(|model| TextDetector::from_model(model, Default::default()))
+    pub fn new(params: OcrEngineParams) -> Result<OcrEngine, OcrError> {
+        let memory_map = params.memory_map;
+        if memory_map && !cfg!(feature = "mmap") {
+            return Err(OcrError::InvalidConfig(
+                "memory mapping models requires the `mmap` feature".into(),
+            ));
+        }
+
+        let load_model = |model: Option<Model>, source: Option<ModelSource>| match model {
+            Some(model) => Ok(Some(model)),
+            None => source.map(|source| source.load(memory_map)).transpose(),
+        };
+        let detection_model = load_model(params.detection_model, params.detection_model_source)?;
+        let recognition_model =
+            load_model(params.recognition_model, params.recognition_model_source)?;
+
+        let detector = detection_model
+            .map(|model| TextDetector::from_model(model, params.detection_params.clone()))
             .transpose()?;
-        let recognizer = params
-            .recognition_model
-            .map(TextRecognizer::from_model)
+        let recognizer = recognition_model
+            .map(|model| {
+                TextRecognizer::from_model(
+                    model,
+                    params.recognition_params.clone(),
+                    params.alphabet.clone(),
+                )
+            })
             .transpose()?;
 
-        let alphabet = params
-            .alphabet
-            .unwrap_or_else(|| DEFAULT_ALPHABET.to_string());
+        let alphabet = match recognizer.as_ref() {
+            Some(recognizer) => recognizer.alphabet(),
+            None => params.alphabet.as_deref().unwrap_or(DEFAULT_ALPHABET),
+        };
 
         let excluded_char_labels = params.allowed_chars.map(|allowed_characters| {
             alphabet
@@ -120,32 +326,99 @@ impl OcrEngine {
                 .collect::<Vec<_>>()
         });
 
+        let trace_sink = params.trace_sink.or_else(|| {
+            params
+                .debug
+                .then(|| Arc::new(LogTraceSink) as Arc<dyn TraceSink>)
+        });
+
         Ok(OcrEngine {
             detector,
             recognizer,
-            alphabet,
             excluded_char_labels,
             debug: params.debug,
             decode_method: params.decode_method,
+            trace_sink,
+            layout_options: params.layout_options,
+            body_text_only: params.body_text_only,
         })
     }
 
     /// 预处理图像以便使用引擎的其他方法。
-    pub fn prepare_input(&self, image: ImageSource) -> anyhow::Result<OcrInput> {
+    ///
+    /// 如果图像的宽度或高度为零，则返回错误。
+    pub fn prepare_input(&self, image: ImageSource) -> Result<OcrInput, OcrError> {
+        let mut span = Span::enter(self.trace_sink(), TraceStage::Preprocess);
+        let [_, height, width] = image.shape();
+        span.record("width", width);
+        span.record("height", height);
+        if height == 0 || width == 0 {
+            return Err(OcrError::InvalidInput(format!(
+                "image has zero size ({}x{})",
+                width, height
+            )));
+        }
+        let image = prepare_image(image);
         Ok(OcrInput {
-            image: prepare_image(image),
+            image,
+            preprocess_time: span.elapsed(),
         })
     }
 
+    /// 解码图像文件的内容，并将其中的每一页预处理为文档的一页。
+    ///
+    /// 支持 PNG、JPEG、WebP、BMP、GIF 和 TIFF 格式。多页 TIFF 文件以及 PNG、WebP 和 GIF
+    /// 动画的每一帧都会成为文档中的一页。图像会根据 EXIF 或 TIFF 方向元数据旋转为正向。
+    /// 各页会并行预处理。每一页所应用的方向变换会记录在文档中，并通过
+    /// [PageResult::orientation] 报告；使用 [PageResult::to_raw_rect] 可将识别结果的坐标
+    /// 映射回文件中原始的像素网格。
+    ///
+    /// 需要启用 `decode` 功能。
+    #[cfg(feature = "decode")]
+    pub fn prepare_document(&self, data: &[u8]) -> Result<OcrDocument, OcrError> {
+        let pages = decode::decode_pages(data)?;
+        let inputs = pages
+            .par_iter()
+            .map(|page| self.prepare_input(page.to_image_source()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut document = OcrDocument::new();
+        for (input, page) in inputs.into_iter().zip(&pages) {
+            document.push_oriented_page(input, page.orientation());
+        }
+        Ok(document)
+    }
+
+    /// 读取并解码图像文件，参见 [OcrEngine::prepare_document]。
+    ///
+    /// 需要启用 `decode` 功能。
+    #[cfg(feature = "decode")]
+    pub fn load_document(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<OcrDocument, OcrError> {
+        let data = std::fs::read(path).map_err(decode::DecodeError::from)?;
+        self.prepare_document(&data)
+    }
+
     /// 检测图像中的文本词。
     ///
     /// 返回检测到的每个词的旋转边界矩形的无序列表。
-    pub fn detect_words(&self, input: &OcrInput) -> anyhow::Result<Vec<RotatedRect>> {
-        if let Some(detector) = self.detector.as_ref() {
-            detector.detect_words(input.image.view(), self.debug)
-        } else {
-            Err(anyhow!("检测模型未加载"))
-        }
+    pub fn detect_words(&self, input: &OcrInput) -> Result<Vec<RotatedRect>, OcrError> {
+        self.detect_words_with_sink(input, self.trace_sink())
+    }
+
+    fn detect_words_with_sink(
+        &self,
+        input: &OcrInput,
+        sink: Option<&dyn TraceSink>,
+    ) -> Result<Vec<RotatedRect>, OcrError> {
+        let Some(detector) = self.detector.as_ref() else {
+            return Err(OcrError::ModelMissing(ModelKind::Detection));
+        };
+        let mut span = Span::enter(sink, TraceStage::Detection);
+        let words = detector.detect_words(input.image.view(), self.debug)?;
+        span.record("words", words.len());
+        Ok(words)
     }
 
     /// 检测图像中的文本像素。
@@ -153,11 +426,11 @@ impl OcrEngine {
     /// 返回一个 (H, W) 张量，指示输入图像中每个像素是文本词一部分的概率。
     /// 这是一个有用的调试 API。使用 [detect_words](OcrEngine::detect_words)
     /// 可以获取包含文本词的旋转边界框的更高级 API。
-    pub fn detect_text_pixels(&self, input: &OcrInput) -> anyhow::Result<NdTensor<f32, 2>> {
+    pub fn detect_text_pixels(&self, input: &OcrInput) -> Result<NdTensor<f32, 2>, OcrError> {
         if let Some(detector) = self.detector.as_ref() {
             detector.detect_text_pixels(input.image.view(), self.debug)
         } else {
-            Err(anyhow!("检测模型未加载"))
+            Err(OcrError::ModelMissing(ModelKind::Detection))
         }
     }
 
@@ -165,12 +438,69 @@ impl OcrEngine {
     ///
     /// `words` 是 [OcrEngine::detect_words] 找到的文本词矩形的无序列表。
     /// 结果是按阅读顺序排序的行列表。每一行是按阅读顺序排序的词边界矩形序列。
+    ///
+    /// 如果设置了 [LayoutOptions::use_ruling_lines]，图像中印刷的水平和竖直分隔线
+    /// （例如分栏线和表格线）会与词之间的空白一起，用作分隔文本行和段落的边界。
+    /// 查找分隔线需要扫描整幅图像，因此默认关闭。
+    ///
+    /// 使用 [OcrEngineParams::layout_options] 中的布局选项。
+    ///
+    /// 如果某个词矩形的坐标不是有限值，或远离图像边界，则返回错误。
     pub fn find_text_lines(
         &self,
-        _input: &OcrInput,
+        input: &OcrInput,
+        words: &[RotatedRect],
+    ) -> Result<Vec<Vec<RotatedRect>>, OcrError> {
+        self.find_text_lines_with_sink(input, words, &self.layout_options, self.trace_sink())
+    }
+
+    /// 与 [OcrEngine::find_text_lines] 相同，但使用 `opts` 代替引擎的默认布局选项。
+    ///
+    /// 这可以为已知类型的文档修正阅读顺序，例如指定栏数，或排除页眉和页脚。
+    /// 位于排除区域内的词不会出现在结果中。
+    ///
+    /// 除了 [OcrEngine::find_text_lines] 的错误外，如果某条分隔线的坐标不是有限值，也会返回错误。
+    pub fn find_text_lines_with_options(
+        &self,
+        input: &OcrInput,
+        words: &[RotatedRect],
+        opts: &LayoutOptions,
+    ) -> Result<Vec<Vec<RotatedRect>>, OcrError> {
+        self.find_text_lines_with_sink(input, words, opts, self.trace_sink())
+    }
+
+    fn find_text_lines_with_sink(
+        &self,
+        input: &OcrInput,
+        words: &[RotatedRect],
+        opts: &LayoutOptions,
+        sink: Option<&dyn TraceSink>,
+    ) -> Result<Vec<Vec<RotatedRect>>, OcrError> {
+        self.analyze_layout(input, words, opts, sink)
+            .map(|(lines, _rulings)| lines)
+    }
+
+    /// 查找文本行，并返回布局分析使用的印刷分隔线。
+    fn analyze_layout(
+        &self,
+        input: &OcrInput,
         words: &[RotatedRect],
-    ) -> Vec<Vec<RotatedRect>> {
-        find_text_lines(words)
+        opts: &LayoutOptions,
+        sink: Option<&dyn TraceSink>,
+    ) -> Result<(Vec<Vec<RotatedRect>>, Vec<LineF>), OcrError> {
+        let mut span = Span::enter(sink, TraceStage::Layout);
+        span.record("words", words.len());
+        validate_word_rects(input, words)?;
+        validate_separators(&opts.separators)?;
+        let rulings = if opts.use_ruling_lines {
+            self.find_ruling_lines(input)
+        } else {
+            Vec::new()
+        };
+        span.record("rulings", rulings.len());
+        let lines = find_text_lines(words, &rulings, opts);
+        span.record("lines", lines.len());
+        Ok((lines, rulings))
     }
 
     /// 识别图像中的文本行。
@@ -182,23 +512,48 @@ impl OcrEngine {
         &self,
         input: &OcrInput,
         lines: &[Vec<RotatedRect>],
-    ) -> anyhow::Result<Vec<Option<TextLine>>> {
+    ) -> Result<Vec<Option<TextLine>>, OcrError> {
         if let Some(recognizer) = self.recognizer.as_ref() {
             recognizer.recognize_text_lines(
                 input.image.view(),
                 lines,
-                RecognitionOpt {
-                    debug: self.debug,
-                    decode_method: self.decode_method,
-                    alphabet: &self.alphabet,
-                    excluded_char_labels: self.excluded_char_labels.as_deref(),
-                },
+                self.recognition_opts(self.trace_sink()),
             )
         } else {
-            Err(anyhow!("识别模型未加载"))
+            Err(OcrError::ModelMissing(ModelKind::Recognition))
         }
     }
 
+    /// 识别图像中的文本行，并在每行所在的识别批次完成后立即报告结果。
+    ///
+    /// 这是 [recognize_text](OcrEngine::recognize_text) 的流式变体，适用于在长文档被识别时逐步显示文本。
+    /// 对于 `lines` 中的每一行，`on_line` 会以 `(line_index, text_line)` 被调用一次，
+    /// 其中 `line_index` 是该行在 `lines` 中的索引。
+    ///
+    /// 识别批次是并行处理的，因此行的报告顺序是任意的。对 `on_line` 的调用是串行的。
+    /// 如果识别失败，部分行的结果可能已经被报告。
+    pub fn recognize_text_streaming<F>(
+        &self,
+        input: &OcrInput,
+        lines: &[Vec<RotatedRect>],
+        on_line: F,
+    ) -> Result<(), OcrError>
+    where
+        F: FnMut(usize, Option<TextLine>) + Send,
+    {
+        let Some(recognizer) = self.recognizer.as_ref() else {
+            return Err(OcrError::ModelMissing(ModelKind::Recognition));
+        };
+        let on_line = Mutex::new(on_line);
+        recognizer.recognize_text_lines_streaming(
+            &[(input.image.view(), lines)],
+            self.recognition_opts(self.trace_sink()),
+            |_image_index, line_index, text_line| {
+                (on_line.lock().unwrap_or_else(PoisonError::into_inner))(line_index, text_line);
+            },
+        )
+    }
+
     /// 准备图像以便输入到文本行识别模型。
     ///
     /// 此方法存在以帮助调试识别问题，通过暴露 [OcrEngine::recognize_text] 在将其输入到识别模型之前所做的预处理。
@@ -211,12 +566,96 @@ impl OcrEngine {
         &self,
         input: &OcrInput,
         line: &[RotatedRect],
-    ) -> anyhow::Result<NdTensor<f32, 2>> {
+    ) -> Result<NdTensor<f32, 2>, OcrError> {
         let Some(recognizer) = self.recognizer.as_ref() else {
-            return Err(anyhow!("识别模型未加载"));
+            return Err(OcrError::ModelMissing(ModelKind::Recognition));
         };
-        let line_image = recognizer.prepare_input(input.image.view(), line);
-        Ok(line_image)
+        recognizer.prepare_input(input.image.view(), line)
+    }
+
+    /// 将文本检测和布局分析的结果绘制到输入图像的副本上，用于诊断检测和布局问题。
+    ///
+    /// 根据 `opts`，图像中会绘制文本概率热图、词矩形、文本行多边形、文本块分隔区域和分隔线，
+    /// 以及按阅读顺序连接各行的箭头。词、行和分隔符与 [OcrEngine::detect_words] 和
+    /// [OcrEngine::find_text_lines] 的结果相同，包括引擎的 [LayoutOptions]。
+    /// 启用 `png` 功能后，结果可以通过 `DebugImage::encode_png` 编码为 PNG；也可以直接使用其
+    /// RGBA 像素数据。
+    pub fn render_debug(
+        &self,
+        input: &OcrInput,
+        opts: &DebugRenderOptions,
+    ) -> Result<DebugImage, OcrError> {
+        let Some(detector) = self.detector.as_ref() else {
+            return Err(OcrError::ModelMissing(ModelKind::Detection));
+        };
+        let text_pixels = if opts.heat_map {
+            Some(detector.detect_text_pixels(input.image.view(), self.debug)?)
+        } else {
+            None
+        };
+        let words = self.detect_words(input)?;
+        let layout_opts = &self.layout_options;
+        let (lines, rulings) =
+            self.analyze_layout(input, &words, layout_opts, self.trace_sink())?;
+        let separators = find_layout_separators(&words, &rulings, layout_opts);
+
+        let layers = DebugLayers {
+            text_pixels: text_pixels.as_ref().map(|p| p.view()),
+            words: &words,
+            lines: &lines,
+            separators: &separators.blocks,
+            line_separators: &separators.lines,
+        };
+        Ok(render_debug_image(input.image.view(), &layers, opts))
+    }
+
+    /// 在已识别的文本行中查找表格，并按行和单元格返回其中的文本行。
+    ///
+    /// `lines` 是 [OcrEngine::recognize_text] 或 [OcrEngine::get_text] 流程返回的文本行。
+    /// 表格的列由图像中的竖直分隔线确定，没有分隔线时由文本片段之间的空白确定；
+    /// 水平分隔线用于将多行文本归入同一个单元格。
+    /// 可以使用 [Table::to_csv] 或 [Table::to_json] 导出表格内容。
+    pub fn find_tables(
+        &self,
+        input: &OcrInput,
+        lines: &[Option<TextLine>],
+    ) -> Result<Vec<Table>, OcrError> {
+        let rulings = self.find_ruling_lines(input);
+        Ok(tables::find_tables(lines, &rulings))
+    }
+
+    /// 查找图像中印刷的水平和竖直分隔线。
+    fn find_ruling_lines(&self, input: &OcrInput) -> Vec<LineF> {
+        let image = input.image.slice(0);
+        let [height, width] = image.shape();
+        find_ruling_lines(image, default_min_length(height, width))
+    }
+
+    /// 对图像运行完整的 OCR 流程，并将结果与 `truth` 进行比较。
+    ///
+    /// 返回的 [EvalMetrics] 可以通过 [EvalMetrics::add] 在多张图像之间累加，
+    /// 然后计算字符错误率、词错误率、检测精确率/召回率/F1 以及阅读顺序准确率。
+    /// 检测到的词与真实词框按 IoU 匹配，阈值为 `iou_threshold`
+    /// （参见 [DEFAULT_IOU_THRESHOLD]）。
+    pub fn evaluate(
+        &self,
+        input: &OcrInput,
+        truth: &GroundTruth,
+        iou_threshold: f32,
+    ) -> Result<EvalMetrics, OcrError> {
+        let words = self.detect_words(input)?;
+        let line_rects = self.find_text_lines(input, &words)?;
+        let lines: Vec<String> = self
+            .recognize_text(input, &line_rects)?
+            .into_iter()
+            .filter_map(|line| line.map(|l| l.to_string()))
+            .collect();
+        Ok(EvalMetrics::from_results(
+            &lines,
+            &words,
+            truth,
+            iou_threshold,
+        ))
     }
 
     /// 返回应用于文本检测模型输出的置信度阈值以确定像素是否为文本。
@@ -228,33 +667,225 @@ impl OcrEngine {
     }
 
     /// 方便的 API 以提取图像中的所有文本为单个字符串。
-    pub fn get_text(&self, input: &OcrInput) -> anyhow::Result<String> {
-        let word_rects = self.detect_words(input)?;
-        let line_rects = self.find_text_lines(input, &word_rects);
-        let text = self
-            .recognize_text(input, &line_rects)?
+    pub fn get_text(&self, input: &OcrInput) -> Result<String, OcrError> {
+        self.get_text_with_sink(input, self.trace_sink())
+    }
+
+    /// 与 [get_text](OcrEngine::get_text) 相同，但同时返回本次调用各处理阶段的统计信息。
+    ///
+    /// 统计信息包括 [OcrEngine::prepare_input] 创建 `input` 所用的时间。
+    pub fn get_text_with_stats(&self, input: &OcrInput) -> Result<(String, OcrStats), OcrError> {
+        let collector = StatsCollector::new(self.trace_sink());
+        let text = self.get_text_with_sink(input, Some(&collector))?;
+        let mut stats = collector.into_stats();
+        stats.preprocess_time += input.preprocess_time;
+        Ok((text, stats))
+    }
+
+    fn get_text_with_sink(
+        &self,
+        input: &OcrInput,
+        sink: Option<&dyn TraceSink>,
+    ) -> Result<String, OcrError> {
+        let word_rects = self.detect_words_with_sink(input, sink)?;
+        let line_rects =
+            self.find_text_lines_with_sink(input, &word_rects, &self.layout_options, sink)?;
+        let Some(recognizer) = self.recognizer.as_ref() else {
+            return Err(OcrError::ModelMissing(ModelKind::Recognition));
+        };
+        let mut lines = recognizer.recognize_text_lines(
+            input.image.view(),
+            &line_rects,
+            self.recognition_opts(sink),
+        )?;
+        if self.body_text_only {
+            self.exclude_non_body_lines(input, &mut lines);
+        }
+        let text = lines
             .into_iter()
             .filter_map(|line| line.map(|l| l.to_string()))
             .collect::<Vec<_>>()
             .join("\n");
         Ok(text)
     }
+
+    /// 按在页面中的作用对已识别的文本行进行分类，例如页眉、页脚、页码、正文、
+    /// 图表标题或页边注释。
+    ///
+    /// `pages` 中的每个条目包含一页的输入图像和按阅读顺序排列的文本行。
+    /// 结果中每页对应一个条目，其中每个文本行对应一个 [TextRole]。
+    /// 分类依据文本在页面中的位置和内容；同时对多页进行分类时，
+    /// 还会利用在多页相同位置重复出现的文本来识别页眉和页脚。
+    pub fn classify_lines(&self, pages: &[(&OcrInput, &[Option<TextLine>])]) -> Vec<Vec<TextRole>> {
+        let pages: Vec<_> = pages
+            .iter()
+            .map(|(input, lines)| {
+                let [_, height, width] = input.image.shape();
+                PageLines {
+                    height,
+                    width,
+                    lines,
+                }
+            })
+            .collect();
+        classify_lines(&pages)
+    }
+
+    /// 将 `lines` 中不属于正文的文本行替换为 `None`。
+    fn exclude_non_body_lines(&self, input: &OcrInput, lines: &mut [Option<TextLine>]) {
+        let roles = self.classify_lines(&[(input, lines)]).remove(0);
+        for (line, role) in lines.iter_mut().zip(roles) {
+            if role != TextRole::Body {
+                *line = None;
+            }
+        }
+    }
+
+    /// 检测并识别多张图像中的文本。
+    ///
+    /// 这等效于对每个输入依次调用 [detect_words](OcrEngine::detect_words)、
+    /// [find_text_lines](OcrEngine::find_text_lines) 和
+    /// [recognize_text](OcrEngine::recognize_text)，但检测和布局分析会并行运行，
+    /// 并且所有图像中的文本行会被汇集到按宽度分组的识别批次中。
+    /// 当处理大量只包含少量文本行的小图像（例如收据）时，这可以显著提高吞吐量。
+    ///
+    /// 结果中每个输入对应一个条目，包含该图像中按阅读顺序排列的文本行。
+    /// 如果设置了 [OcrEngineParams::body_text_only]，每张图像中不属于正文的文本行
+    /// 会被替换为 `None`。
+    pub fn process_batch(
+        &self,
+        inputs: &[OcrInput],
+    ) -> Result<Vec<Vec<Option<TextLine>>>, OcrError> {
+        self.process_batch_with_sink(inputs, self.trace_sink())
+    }
+
+    /// 与 [process_batch](OcrEngine::process_batch) 相同，但同时返回本次调用各处理阶段的统计信息。
+    ///
+    /// 统计信息汇总了所有输入图像，包括 [OcrEngine::prepare_input] 创建它们所用的时间。
+    pub fn process_batch_with_stats(
+        &self,
+        inputs: &[OcrInput],
+    ) -> Result<(BatchLines, OcrStats), OcrError> {
+        let collector = StatsCollector::new(self.trace_sink());
+        let lines = self.process_batch_with_sink(inputs, Some(&collector))?;
+        let mut stats = collector.into_stats();
+        stats.preprocess_time += inputs.iter().map(|input| input.preprocess_time).sum();
+        Ok((lines, stats))
+    }
+
+    fn process_batch_with_sink(
+        &self,
+        inputs: &[OcrInput],
+        sink: Option<&dyn TraceSink>,
+    ) -> Result<Vec<Vec<Option<TextLine>>>, OcrError> {
+        let mut batch_lines = self.recognize_batch_with_sink(inputs, sink)?;
+        if self.body_text_only {
+            for (input, lines) in inputs.iter().zip(&mut batch_lines) {
+                self.exclude_non_body_lines(input, lines);
+            }
+        }
+        Ok(batch_lines)
+    }
+
+    /// 检测并识别多张图像中的所有文本行。
+    fn recognize_batch_with_sink(
+        &self,
+        inputs: &[OcrInput],
+        sink: Option<&dyn TraceSink>,
+    ) -> Result<Vec<Vec<Option<TextLine>>>, OcrError> {
+        let Some(recognizer) = self.recognizer.as_ref() else {
+            return Err(OcrError::ModelMissing(ModelKind::Recognition));
+        };
+
+        let line_rects = inputs
+            .par_iter()
+            .map(|input| {
+                let word_rects = self.detect_words_with_sink(input, sink)?;
+                self.find_text_lines_with_sink(input, &word_rects, &self.layout_options, sink)
+            })
+            .collect::<Result<Vec<_>, OcrError>>()?;
+
+        let images: Vec<_> = inputs
+            .iter()
+            .zip(line_rects.iter())
+            .map(|(input, lines)| (input.image.view(), lines.as_slice()))
+            .collect();
+        recognizer.recognize_text_lines_batch(&images, self.recognition_opts(sink))
+    }
+
+    /// 检测并识别多页文档中的文本。
+    ///
+    /// 各页会像 [process_batch](OcrEngine::process_batch) 一样并行处理。
+    /// 结果中每页对应一个 [PageResult]，包含页索引、按阅读顺序排列的文本行及其 [TextRole]。
+    /// 页眉和页脚的检测会利用在多页中重复出现的文本。
+    /// 使用 [DocumentResult::body_text] 可以获取跨页合并连字符断词后的正文文本。
+    /// 结果总是包含所有文本行，[OcrEngineParams::body_text_only] 会被忽略。
+    pub fn process_document(&self, document: &OcrDocument) -> Result<DocumentResult, OcrError> {
+        let page_lines = self.recognize_batch_with_sink(document.pages(), self.trace_sink())?;
+        let pages: Vec<_> = document
+            .pages()
+            .iter()
+            .zip(&page_lines)
+            .map(|(input, lines)| (input, lines.as_slice()))
+            .collect();
+        let page_roles = self.classify_lines(&pages);
+
+        let pages = page_lines
+            .into_iter()
+            .zip(page_roles)
+            .enumerate()
+            .map(|(index, (lines, roles))| {
+                let [_, height, width] = document.pages()[index].image.shape();
+                PageResult {
+                    index,
+                    lines,
+                    roles,
+                    width: width as u32,
+                    height: height as u32,
+                    orientation: document.orientations()[index],
+                }
+            })
+            .collect();
+        Ok(DocumentResult { pages })
+    }
+
+    /// 返回传递给识别模型的选项。
+    fn recognition_opts<'a>(&'a self, sink: Option<&'a dyn TraceSink>) -> RecognitionOpt<'a> {
+        RecognitionOpt {
+            decode_method: self.decode_method,
+            excluded_char_labels: self.excluded_char_labels.as_deref(),
+            trace_sink: sink,
+        }
+    }
+
+    /// 返回接收处理阶段事件的 sink（如果有）。
+    fn trace_sink(&self) -> Option<&dyn TraceSink> {
+        self.trace_sink.as_deref()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::error::Error;
     use std::ops::RangeFull;
+    use std::sync::{Arc, Mutex};
 
     use rten::model_builder::{ModelBuilder, ModelFormat, OpType};
     use rten::ops::{MaxPool, Transpose};
     use rten::Dimension;
     use rten::Model;
-    use rten_imageproc::{fill_rect, BoundingRect, Rect, RectF, RotatedRect};
+    use rten_imageproc::{
+        fill_rect, BoundingRect, LineF, Point, PointF, Rect, RectF, RotatedRect, Vec2,
+    };
     use rten_tensor::prelude::*;
     use rten_tensor::{NdTensor, NdTensorView, Tensor};
 
-    use super::{DimOrder, ImageSource, OcrEngine, OcrEngineParams, DEFAULT_ALPHABET};
+    use super::{
+        DebugImage, DebugRenderOptions, DimOrder, ImageSource, LayoutOptions, ModelKind,
+        OcrDocument, OcrEngine, OcrEngineParams, OcrError, RecognitionParams, TextDetectorParams,
+        TextLine, TextRole, TraceEvent, TraceSink, TraceStage, DEFAULT_ALPHABET,
+    };
+    use crate::test_util::PageBuilder;
 
     /// 生成一个用于 OCR 处理的 CHW 输入图像。
     ///
@@ -279,6 +910,17 @@ mod tests {
     ///
     /// 接受一个 [-0.5, 0.5] 值的 CHW 输入张量，并加上 +0.5 偏置以生成输出“概率图”。
     fn fake_detection_model() -> Model {
+        Model::load(fake_detection_model_data()).unwrap()
+    }
+
+    /// 返回 [fake_detection_model] 的序列化 `.rten` 数据。
+    fn fake_detection_model_data() -> Vec<u8> {
+        // 实际模型使用较大的输入 (800x600)。虚假模型使用较小的输入以加快测试速度。
+        fake_detection_model_data_with_dims(Dimension::Fixed(200), Dimension::Fixed(100))
+    }
+
+    /// 返回输入高度和宽度为指定维度的虚假文本检测模型的序列化数据。
+    fn fake_detection_model_data_with_dims(height: Dimension, width: Dimension) -> Vec<u8> {
         let mut gb = ModelBuilder::new(ModelFormat::V1);
 
         let input_id = gb.add_value(
@@ -286,9 +928,8 @@ mod tests {
             Some(&[
                 Dimension::Symbolic("batch".to_string()),
                 Dimension::Fixed(1),
-                // 实际模型使用较大的输入 (800x600)。虚假模型使用较小的输入以加快测试速度。
-                Dimension::Fixed(200),
-                Dimension::Fixed(100),
+                height,
+                width,
             ]),
         );
         gb.add_input(input_id);
@@ -305,8 +946,7 @@ mod tests {
             &[output_id],
         );
 
-        let model_data = gb.finish();
-        Model::load(model_data).unwrap()
+        gb.finish()
     }
 
     /// 创建一个虚假的文本识别模型。
@@ -355,7 +995,14 @@ mod tests {
         );
 
         // Transpose: NHW/4 => W/4NH
-        let transpose_out = gb.add_value("transpose_out", None);
+        let transpose_out = gb.add_value(
+            "transpose_out",
+            Some(&[
+                Dimension::Symbolic("seq".to_string()),
+                Dimension::Symbolic("batch".to_string()),
+                Dimension::Fixed(output_columns),
+            ]),
+        );
         gb.add_operator(
             "transpose",
             OpType::Transpose(Transpose {
@@ -374,6 +1021,22 @@ mod tests {
         (model, alphabet)
     }
 
+    /// 使用虚假的检测和识别模型创建引擎。
+    fn fake_engine() -> Result<OcrEngine, OcrError> {
+        fake_engine_with(OcrEngineParams::default())
+    }
+
+    /// 使用虚假的检测和识别模型创建引擎，其余参数取自 `params`。
+    fn fake_engine_with(params: OcrEngineParams) -> Result<OcrEngine, OcrError> {
+        let (rec_model, alphabet) = fake_recognition_model();
+        OcrEngine::new(OcrEngineParams {
+            detection_model: Some(fake_detection_model()),
+            recognition_model: Some(rec_model),
+            alphabet: Some(alphabet),
+            ..params
+        })
+    }
+
     /// 返回由 `gen_test_image(3)` 生成的图像的预期词位置。
     ///
     /// 输出框比输入图像中的略大。这是因为实际的检测模型被训练成预测的框比真实框略小，
@@ -381,9 +1044,9 @@ mod tests {
     fn expected_word_boxes() -> Vec<RectF> {
         let [top, height] = [27, 25];
         [
-            Rect::from_tlhw(top, -3, height, 56).to_f32(),
-            Rect::from_tlhw(top, 66, height, 57).to_f32(),
-            Rect::from_tlhw(top, 136, height, 57).to_f32(),
+            Rect::from_tlhw(top, -3, height, 55).to_f32(),
+            Rect::from_tlhw(top, 67, height, 55).to_f32(),
+            Rect::from_tlhw(top, 137, height, 55).to_f32(),
         ]
         .into()
     }
@@ -431,6 +1094,142 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_ocr_engine_detect_words_multi_scale() -> Result<(), Box<dyn Error>> {
+        let image = gen_test_image(3 /* n_words */);
+        let engine = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(fake_detection_model()),
+            detection_params: TextDetectorParams {
+                scales: vec![1., 2.],
+                ..Default::default()
+            },
+            ..Default::default()
+        })?;
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        // 在原始分辨率下，图像被划分为多个图块。同一个词在整图和图块中被重复检测到，
+        // 合并后应只保留一个。被图块边缘截断的词会被丢弃。
+        let mut boxes: Vec<RectF> = engine
+            .detect_words(&input)?
+            .into_iter()
+            .map(|rotated_rect| rotated_rect.bounding_rect())
+            .collect();
+        boxes.sort_by_key(|b| [b.top() as i32, b.left() as i32]);
+        assert_eq!(boxes, expected_word_boxes());
+
+        let err = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(fake_detection_model()),
+            detection_params: TextDetectorParams {
+                scales: vec![0.],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert!(matches!(err, Err(OcrError::InvalidConfig(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_detect_words_symbolic_dims() -> Result<(), Box<dyn Error>> {
+        let image = gen_test_image(3 /* n_words */);
+        let model_data = fake_detection_model_data_with_dims(
+            Dimension::Symbolic("height".to_string()),
+            Dimension::Symbolic("width".to_string()),
+        );
+        let detect_boxes = |params: TextDetectorParams| -> Result<Vec<RectF>, Box<dyn Error>> {
+            let engine = OcrEngine::new(OcrEngineParams {
+                detection_model: Some(Model::load(model_data.clone())?),
+                detection_params: params,
+                ..Default::default()
+            })?;
+            let input =
+                engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+            let mut boxes: Vec<RectF> = engine
+                .detect_words(&input)?
+                .into_iter()
+                .map(|rotated_rect| rotated_rect.bounding_rect())
+                .collect();
+            boxes.sort_by_key(|b| [b.top() as i32, b.left() as i32]);
+            Ok(boxes)
+        };
+
+        // 图像小于最大输入尺寸，因此不会被缩放，只会被填充。
+        assert_eq!(detect_boxes(Default::default())?, expected_word_boxes());
+
+        // 图像按比例缩小到 50x100。
+        let boxes = detect_boxes(TextDetectorParams {
+            max_input_size: 100,
+            ..Default::default()
+        })?;
+        assert_eq!(boxes.len(), 3);
+        for (actual, expected) in boxes.iter().zip(expected_word_boxes()) {
+            for (a, e) in [
+                (actual.top(), expected.top()),
+                (actual.left(), expected.left()),
+                (actual.bottom(), expected.bottom()),
+                (actual.right(), expected.right()),
+            ] {
+                assert!((a - e).abs() <= 2., "{:?} != {:?}", actual, expected);
+            }
+        }
+
+        let err = detect_boxes(TextDetectorParams {
+            input_size_multiple: 0,
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<OcrError>(),
+            Some(OcrError::InvalidConfig(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_model_sources() -> Result<(), Box<dyn Error>> {
+        let temp_dir = tempfile::tempdir()?;
+        let model_path = temp_dir.path().join("detection.rten");
+        std::fs::write(&model_path, fake_detection_model_data())?;
+
+        let image = gen_test_image(3 /* n_words */);
+        for params in [
+            OcrEngineParams::default().with_detection_model(fake_detection_model_data()),
+            OcrEngineParams::default().with_detection_model(model_path.clone()),
+        ] {
+            let engine = OcrEngine::new(params)?;
+            let input =
+                engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+            assert_eq!(engine.detect_words(&input)?.len(), 3);
+        }
+
+        // 只有启用 `mmap` 功能时才能使用内存映射。
+        let result = OcrEngine::new(OcrEngineParams {
+            detection_model_source: Some(model_path.clone().into()),
+            memory_map: true,
+            ..Default::default()
+        });
+        if cfg!(feature = "mmap") {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(result, Err(OcrError::InvalidConfig(_))));
+        }
+        std::fs::remove_file(&model_path)?;
+
+        let err =
+            OcrEngine::new(OcrEngineParams::default().with_recognition_model(model_path.clone()))
+                .err()
+                .unwrap();
+        assert!(matches!(
+            &err,
+            OcrError::ModelLoadFailed { path: Some(path), .. } if *path == model_path
+        ));
+        assert!(err.source().is_some());
+
+        Ok(())
+    }
+
     // 使用一个虚假的识别模型测试识别。
     //
     // 虚假模型将输入图像的每一列视为字符类概率向量。预处理会将值从 [0, 1] 转换为 [-0.5, 0.5]。
@@ -486,13 +1285,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_ocr_engine_recognize_long_line() -> Result<(), Box<dyn Error>> {
+        // 每个字符占 12 列，字符之间有 8 列 CTC 空字符，以便重复的字符不会被合并。
+        let text = "12 34 56 78 90 12 34";
+        let char_width = 20;
+        let mut image = NdTensor::zeros([1, 64, text.len() * char_width]);
+        image.slice_mut::<1, _>((0, 0, ..)).fill(1.0);
+        for (i, ch) in text.chars().enumerate() {
+            let label = DEFAULT_ALPHABET.find(ch).unwrap() + 1;
+            let cols = i * char_width..i * char_width + 12;
+            image.slice_mut::<1, _>((0, 0, cols.clone())).fill(0.);
+            image.slice_mut::<1, _>((0, label, cols)).fill(1.0);
+        }
+
+        // 该行比 `max_line_width` 更宽，因此会被拆分为多个重叠的块。改变最大宽度会移动块的
+        // 边界，块边界处的空格既不应丢失也不应重复。
+        for max_line_width in (100..=200).step_by(10) {
+            let (rec_model, alphabet) = fake_recognition_model();
+            test_recognition(
+                OcrEngineParams {
+                    recognition_model: Some(rec_model),
+                    alphabet: Some(alphabet),
+                    recognition_params: RecognitionParams {
+                        max_line_width,
+                        chunk_overlap: 40,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                image.view(),
+                text,
+            )?;
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_ocr_engine_filter_chars() -> Result<(), Box<dyn Error>> {
         let mut image = NdTensor::zeros([1, 64, 32]);
 
         // 将 "0" 的概率设置为 0.7，"1" 的概率设置为 0.3。
         image.slice_mut::<2,(RangeFull, i32, RangeFull)>((.., 2, ..)).fill(0.7);
-        image.slice_mut::<3,(RangeFull, i32, RangeFull)>((.., 3, ..)).fill(0.3);
+        image.slice_mut::<2,(RangeFull, i32, RangeFull)>((.., 3, ..)).fill(0.3);
 
         let (rec_model, alphabet) = fake_recognition_model();
         test_recognition(
@@ -522,4 +1358,580 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ocr_engine_process_batch() -> Result<(), Box<dyn Error>> {
+        let engine = fake_engine()?;
+
+        let inputs = [1, 3, 0, 2]
+            .map(|n_words| {
+                let image = gen_test_image(n_words);
+                engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)
+            })
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let batch_lines = engine.process_batch(&inputs)?;
+        assert_eq!(batch_lines.len(), inputs.len());
+
+        // 结果应与单独处理每张图像时相同。
+        for (input, lines) in inputs.iter().zip(batch_lines) {
+            let words = engine.detect_words(input)?;
+            let line_rects = engine.find_text_lines(input, &words)?;
+            let expected_lines = engine.recognize_text(input, &line_rects)?;
+
+            let to_strings = |lines: &[Option<TextLine>]| -> Vec<Option<String>> {
+                lines
+                    .iter()
+                    .map(|line| line.as_ref().map(|l| l.to_string()))
+                    .collect()
+            };
+            assert_eq!(to_strings(&lines), to_strings(&expected_lines));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_recognize_text_streaming() -> Result<(), Box<dyn Error>> {
+        let engine = fake_engine()?;
+        let image = gen_test_image(3 /* n_words */);
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        // 将每个词作为单独的行，以生成多个识别结果。
+        let line_rects: Vec<Vec<RotatedRect>> = engine
+            .detect_words(&input)?
+            .into_iter()
+            .map(|word| vec![word])
+            .collect();
+        let expected_lines = engine.recognize_text(&input, &line_rects)?;
+
+        let mut streamed_lines: Vec<(usize, Option<String>)> = Vec::new();
+        engine.recognize_text_streaming(&input, &line_rects, |index, line| {
+            streamed_lines.push((index, line.map(|l| l.to_string())));
+        })?;
+        streamed_lines.sort_by_key(|(index, _)| *index);
+
+        assert_eq!(
+            streamed_lines,
+            expected_lines
+                .iter()
+                .enumerate()
+                .map(|(index, line)| (index, line.as_ref().map(|l| l.to_string())))
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_alphabet_mismatch() {
+        // The fake model produces 64 output columns, which requires an
+        // alphabet of 63 characters.
+        let (rec_model, _) = fake_recognition_model();
+        let result = OcrEngine::new(OcrEngineParams {
+            recognition_model: Some(rec_model),
+            alphabet: Some("0123456789".into()),
+            ..Default::default()
+        });
+        let err = result.err().expect("expected alphabet size error");
+        assert!(matches!(
+            err,
+            OcrError::AlphabetMismatch {
+                alphabet_len: 10,
+                output_classes: 64
+            }
+        ));
+    }
+
+    #[test]
+    fn test_ocr_engine_model_missing() -> Result<(), Box<dyn Error>> {
+        let engine = OcrEngine::new(OcrEngineParams::default())?;
+        let image = gen_test_image(1 /* n_words */);
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        assert!(matches!(
+            engine.detect_words(&input),
+            Err(OcrError::ModelMissing(ModelKind::Detection))
+        ));
+        assert!(matches!(
+            engine.recognize_text(&input, &[]),
+            Err(OcrError::ModelMissing(ModelKind::Recognition))
+        ));
+
+        Ok(())
+    }
+
+    /// 使用随机的小图像和畸形的行矩形调用引擎 API，检查它们返回错误而不是 panic。
+    #[test]
+    fn test_ocr_engine_degenerate_inputs() -> Result<(), Box<dyn Error>> {
+        let engine = fake_engine()?;
+
+        // 宽度或高度为零的图像会被拒绝。
+        for shape in [[3, 0, 10], [3, 10, 0]] {
+            let image = NdTensor::<f32, 3>::zeros(shape);
+            let source = ImageSource::from_tensor(image.view(), DimOrder::Chw)?;
+            assert!(matches!(
+                engine.prepare_input(source),
+                Err(OcrError::InvalidInput(_))
+            ));
+        }
+
+        // 图像数据的错误应保留其类型。
+        let err = OcrError::from(ImageSource::from_bytes(&[0; 3], (2, 2)).err().unwrap());
+        assert!(matches!(err, OcrError::InvalidImage(_)));
+        assert!(err.source().is_some());
+
+        let mut rng = fastrand::Rng::with_seed(1234);
+        let mut rand_coord = || match rng.u8(0..6) {
+            0 => f32::NAN,
+            1 => f32::INFINITY,
+            2 => -1e30,
+            3 => 1e30,
+            4 => 0.,
+            _ => rng.f32() * 100. - 50.,
+        };
+        let bad_rects: Vec<RotatedRect> = (0..50)
+            .map(|_| {
+                RotatedRect::new(
+                    PointF::from_yx(rand_coord(), rand_coord()),
+                    Vec2::from_yx(rand_coord(), rand_coord()),
+                    rand_coord(),
+                    rand_coord(),
+                )
+            })
+            .collect();
+
+        let mut rng = fastrand::Rng::with_seed(5678);
+        for _ in 0..20 {
+            let height = rng.usize(1..20);
+            let width = rng.usize(1..20);
+            let pixels: Vec<f32> = (0..3 * height * width).map(|_| rng.f32()).collect();
+            let image = NdTensor::from_data([3, height, width], pixels);
+            let input =
+                engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+            // 只要不 panic，这些调用成功或返回错误都可以。
+            let _ = engine.get_text(&input);
+            let _ = engine.detect_words(&input);
+            let _ = engine.recognize_text(&input, &[]);
+            let _ = engine.recognize_text(&input, &[Vec::new()]);
+            let _ = engine.prepare_recognition_input(&input, &[]);
+
+            let words: Vec<_> = (0..rng.usize(1..4))
+                .map(|_| bad_rects[rng.usize(..bad_rects.len())])
+                .collect();
+            let _ = engine.find_text_lines(&input, &words);
+            let _ = engine.prepare_recognition_input(&input, &words);
+            let _ = engine.recognize_text(&input, &[words]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_trace_events() -> Result<(), Box<dyn Error>> {
+        #[derive(Default)]
+        struct CollectSink {
+            events: Mutex<Vec<TraceEvent>>,
+        }
+
+        impl TraceSink for CollectSink {
+            fn event(&self, event: &TraceEvent) {
+                self.events.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let sink = Arc::new(CollectSink::default());
+        let engine = fake_engine_with(OcrEngineParams {
+            trace_sink: Some(sink.clone()),
+            ..Default::default()
+        })?;
+
+        let n_words = 3;
+        let image = gen_test_image(n_words);
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+        engine.get_text(&input)?;
+
+        let events = sink.events.lock().unwrap();
+        let event = |stage| events.iter().find(|e| e.stage == stage).unwrap();
+
+        let preprocess = event(TraceStage::Preprocess);
+        assert_eq!(preprocess.field("width"), Some(image.size(2)));
+        assert_eq!(preprocess.field("height"), Some(image.size(1)));
+        assert_eq!(event(TraceStage::Detection).field("words"), Some(n_words));
+        assert_eq!(event(TraceStage::Layout).field("lines"), Some(1));
+        assert_eq!(event(TraceStage::Recognition).field("lines"), Some(1));
+        assert_eq!(event(TraceStage::RecognitionBatch).field("lines"), Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_stats() -> Result<(), Box<dyn Error>> {
+        let engine = fake_engine()?;
+
+        let inputs = [1, 3, 2]
+            .map(|n_words| {
+                let image = gen_test_image(n_words);
+                engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)
+            })
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (text, stats) = engine.get_text_with_stats(&inputs[1])?;
+        assert_eq!(text, engine.get_text(&inputs[1])?);
+        assert_eq!(stats.preprocess_time, inputs[1].preprocess_time);
+        assert_eq!(stats.words, 3);
+        assert_eq!(stats.lines, 1);
+        assert_eq!(stats.recognition_batches, 1);
+        assert!(stats.padding_columns < stats.recognition_columns);
+        assert_eq!(
+            stats.padding_fraction(),
+            stats.padding_columns as f32 / stats.recognition_columns as f32
+        );
+
+        let (_, stats) = engine.process_batch_with_stats(&inputs)?;
+        assert_eq!(
+            stats.preprocess_time,
+            inputs.iter().map(|input| input.preprocess_time).sum()
+        );
+        assert_eq!(stats.words, 6);
+        assert_eq!(stats.lines, 3);
+        assert!(stats.recognition_batches >= 1);
+        assert!(stats.recognition_columns > 0);
+        assert!(stats.padding_columns < stats.recognition_columns);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_render_debug() -> Result<(), Box<dyn Error>> {
+        let engine = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(fake_detection_model()),
+            ..Default::default()
+        })?;
+        let image = gen_test_image(3 /* n_words */);
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        let rendered = engine.render_debug(&input, &DebugRenderOptions::default())?;
+        assert_eq!(rendered.width() as usize, image.size(2));
+        assert_eq!(rendered.height() as usize, image.size(1));
+        assert_eq!(rendered.data().len(), image.size(1) * image.size(2) * 4);
+
+        // 引擎布局选项中的分隔线会与文本块分隔区域一起绘制。
+        let separators_only = DebugRenderOptions {
+            heat_map: false,
+            words: false,
+            lines: false,
+            reading_order: false,
+            ..Default::default()
+        };
+        let separator =
+            LineF::from_endpoints(PointF::from_yx(5., 100.), PointF::from_yx(20., 100.));
+        let separator_engine = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(fake_detection_model()),
+            layout_options: LayoutOptions {
+                separators: vec![separator],
+                ..Default::default()
+            },
+            ..Default::default()
+        })?;
+        let pixel = |rendered: &DebugImage, y: usize, x: usize| -> [u8; 4] {
+            let offset = (y * rendered.width() as usize + x) * 4;
+            rendered.data()[offset..offset + 4].try_into().unwrap()
+        };
+        let plain = engine.render_debug(&input, &separators_only)?;
+        let with_separator = separator_engine.render_debug(&input, &separators_only)?;
+        assert_eq!(pixel(&plain, 10, 100), [0, 0, 0, 255]);
+        assert_eq!(pixel(&with_separator, 10, 100)[0], 255);
+
+        let engine = OcrEngine::new(OcrEngineParams::default())?;
+        assert!(matches!(
+            engine.render_debug(&input, &DebugRenderOptions::default()),
+            Err(OcrError::ModelMissing(ModelKind::Detection))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_detect_synthetic_page() -> Result<(), Box<dyn Error>> {
+        let engine = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(fake_detection_model()),
+            ..Default::default()
+        })?;
+
+        // 页面大小与虚假检测模型的输入大小相同，因此不会被缩放。
+        let page = PageBuilder::new(200, 100, 1)
+            .paragraph(Rect::from_tlhw(10, 10, 180, 80), 12)
+            .build();
+        let image = page.render();
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        let words = engine.detect_words(&input)?;
+        assert_eq!(words.len(), page.words.len());
+
+        let lines = engine.find_text_lines(&input, &words)?;
+        let line_lengths: Vec<_> = lines.iter().map(|l| l.len()).collect();
+        let expected_lengths: Vec<_> = page.lines.iter().map(|l| l.len()).collect();
+        assert_eq!(line_lengths, expected_lengths);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_find_text_lines_ruled_columns() -> Result<(), Box<dyn Error>> {
+        let engine = OcrEngine::new(OcrEngineParams {
+            layout_options: LayoutOptions {
+                use_ruling_lines: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })?;
+
+        // 两栏之间的空白太窄，无法仅靠空白分隔，但它们之间有一条印刷的竖线。
+        let page = PageBuilder::new(400, 600, 6)
+            .columns(Rect::from_tlhw(20, 20, 360, 560), 2, 12, 12)
+            .ruling(Point::from_yx(20, 300), Point::from_yx(380, 300))
+            .build();
+        let image = page.render();
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        let lines = engine.find_text_lines(&input, &page.words)?;
+        assert_eq!(
+            page.line_indices(&lines),
+            page.line_indices(&page.expected_lines())
+        );
+
+        // 默认不查找分隔线，两栏的行会被合并。
+        let lines =
+            engine.find_text_lines_with_options(&input, &page.words, &Default::default())?;
+        assert_ne!(
+            page.line_indices(&lines),
+            page.line_indices(&page.expected_lines())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_find_text_lines_with_options() -> Result<(), Box<dyn Error>> {
+        let page = PageBuilder::new(400, 600, 8)
+            .paragraph(Rect::from_tlhw(10, 20, 12, 200), 10)
+            .columns(Rect::from_tlhw(40, 20, 340, 560), 2, 12, 12)
+            .build();
+        let input = OcrEngine::new(OcrEngineParams::default())?.prepare_input(
+            ImageSource::from_tensor(page.render().view(), DimOrder::Chw)?,
+        )?;
+
+        // 引擎的默认布局选项用于 `find_text_lines`。
+        let opts = LayoutOptions {
+            columns: Some(2),
+            excluded_regions: vec![Rect::from_tlhw(0, 0, 30, 600)],
+            ..Default::default()
+        };
+        let engine = OcrEngine::new(OcrEngineParams {
+            layout_options: opts.clone(),
+            ..Default::default()
+        })?;
+        let mut expected = page.line_indices(&page.expected_lines());
+        expected.remove(0);
+        let lines = engine.find_text_lines(&input, &page.words)?;
+        assert_eq!(page.line_indices(&lines), expected);
+
+        // 显式传入的选项会覆盖默认选项。
+        let lines =
+            engine.find_text_lines_with_options(&input, &page.words, &Default::default())?;
+        assert_eq!(
+            lines.iter().map(|l| l.len()).sum::<usize>(),
+            page.words.len()
+        );
+
+        let invalid_opts = LayoutOptions {
+            separators: vec![LineF::from_endpoints(
+                PointF::from_yx(0., f32::NAN),
+                PointF::from_yx(100., 10.),
+            )],
+            ..opts
+        };
+        assert!(matches!(
+            engine.find_text_lines_with_options(&input, &page.words, &invalid_opts),
+            Err(OcrError::InvalidInput(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_classify_lines() -> Result<(), Box<dyn Error>> {
+        let engine = fake_engine()?;
+        let body_engine = fake_engine_with(OcrEngineParams {
+            body_text_only: true,
+            ..Default::default()
+        })?;
+
+        // 页面顶部边缘有一个页眉词，页面中部有一行正文。
+        let mut image = NdTensor::zeros([3, 300, 200]);
+        for chan_idx in 0..3 {
+            for rect in [
+                Rect::from_tlhw(4, 60, 12, 50),
+                Rect::from_tlhw(140, 0, 20, 50),
+                Rect::from_tlhw(140, 70, 20, 50),
+            ] {
+                fill_rect(image.slice_mut([chan_idx]), rect, 1.);
+            }
+        }
+        let input = engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?;
+
+        let words = engine.detect_words(&input)?;
+        let line_rects = engine.find_text_lines(&input, &words)?;
+        let lines = engine.recognize_text(&input, &line_rects)?;
+        let roles = engine.classify_lines(&[(&input, &lines)]);
+        assert_eq!(roles, [[TextRole::Header, TextRole::Body]]);
+
+        let text = engine.get_text(&input)?;
+        let body_text = body_engine.get_text(&input)?;
+        assert_eq!(text.lines().count(), 2);
+        assert_eq!(Some(body_text.as_str()), text.lines().nth(1));
+
+        // `process_batch` 将页眉行替换为 `None`。
+        let inputs = std::slice::from_ref(&input);
+        let batch_lines = body_engine.process_batch(inputs)?.remove(0);
+        assert_eq!(batch_lines.len(), 2);
+        assert!(batch_lines[0].is_none());
+        assert_eq!(
+            batch_lines[1].as_ref().map(|l| l.to_string()).as_deref(),
+            Some(body_text.as_str())
+        );
+
+        // `process_document` 忽略该选项，返回所有文本行及其作用。
+        let mut document = OcrDocument::new();
+        document.push_page(input);
+        let result = body_engine.process_document(&document)?;
+        assert_eq!(result.pages[0].roles, [TextRole::Header, TextRole::Body]);
+        assert!(result.pages[0].lines.iter().all(|l| l.is_some()));
+        assert_eq!(result.body_text(), body_text);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ocr_engine_process_document() -> Result<(), Box<dyn Error>> {
+        let engine = fake_engine()?;
+
+        let mut document = OcrDocument::new();
+        for n_words in [1, 3, 0, 2] {
+            let image = gen_test_image(n_words);
+            document.push_page(
+                engine.prepare_input(ImageSource::from_tensor(image.view(), DimOrder::Chw)?)?,
+            );
+        }
+        assert_eq!(document.len(), 4);
+
+        let result = engine.process_document(&document)?;
+        let batch_lines = engine.process_batch(document.pages())?;
+
+        // 结果应保留页索引，并与批量处理的结果相同。
+        assert_eq!(result.pages.len(), document.len());
+        let mut page_texts = Vec::new();
+        let mut body_texts = Vec::new();
+        for (index, (page, lines)) in result.pages.iter().zip(batch_lines).enumerate() {
+            assert_eq!(page.index, index);
+            assert_eq!(page.lines.len(), lines.len());
+            assert_eq!(page.roles.len(), lines.len());
+
+            let line_texts = |body_only: bool| -> Vec<String> {
+                lines
+                    .iter()
+                    .zip(&page.roles)
+                    .filter(|(_, role)| !body_only || **role == TextRole::Body)
+                    .filter_map(|(line, _)| line.as_ref().map(|l| l.to_string()))
+                    .collect()
+            };
+            page_texts.push(line_texts(false).join("\n"));
+            body_texts.push(line_texts(true).join("\n"));
+        }
+
+        // 页面之间以空行分隔，没有文本的页面会被跳过。
+        let join_pages = |texts: Vec<String>| -> String {
+            let texts: Vec<_> = texts.into_iter().filter(|t| !t.is_empty()).collect();
+            texts.join("\n\n")
+        };
+        assert_eq!(result.text().split("\n\n").count(), 3);
+        assert_eq!(result.text(), join_pages(page_texts));
+        assert_eq!(result.body_text(), join_pages(body_texts));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn test_ocr_engine_prepare_document() -> Result<(), Box<dyn Error>> {
+        use std::io::Cursor;
+        use tiff::encoder::{colortype, TiffEncoder};
+        use tiff::tags::Tag;
+
+        use super::Orientation;
+
+        // 两页的 TIFF 文件，第二页需要顺时针旋转 90 度。
+        let mut data = Vec::new();
+        let mut encoder = TiffEncoder::new(Cursor::new(&mut data))?;
+        encoder.write_image::<colortype::Gray8>(30, 20, &[255; 30 * 20])?;
+        let mut page = encoder.new_image::<colortype::Gray8>(30, 20)?;
+        page.encoder().write_tag(Tag::Orientation, 6u16)?;
+        page.write_data(&[255; 30 * 20])?;
+
+        let engine = fake_engine()?;
+        let document = engine.prepare_document(&data)?;
+        assert_eq!(
+            document.orientations(),
+            [Orientation::Normal, Orientation::Rotate90]
+        );
+
+        // 页面结果会报告方向和正向图像的尺寸。
+        let result = engine.process_document(&document)?;
+        let sizes: Vec<_> = result
+            .pages
+            .iter()
+            .map(|p| (p.width, p.height, p.orientation))
+            .collect();
+        assert_eq!(
+            sizes,
+            [
+                (30, 20, Orientation::Normal),
+                (20, 30, Orientation::Rotate90)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn test_ocr_engine_load_document_errors() {
+        use super::decode::DecodeError;
+
+        let engine = OcrEngine::new(OcrEngineParams::default()).unwrap();
+
+        // 解码错误应保留其类型，以便区分文件缺失和文件损坏。
+        let err = engine.load_document("does-not-exist.png").err().unwrap();
+        let OcrError::Decode(decode_err) = &err else {
+            panic!("unexpected error {:?}", err);
+        };
+        assert!(matches!(
+            decode_err.downcast_ref::<DecodeError>(),
+            Some(DecodeError::Io(_))
+        ));
+        assert!(err.source().is_some());
+
+        let err = engine.prepare_document(b"not an image").err().unwrap();
+        let OcrError::Decode(decode_err) = &err else {
+            panic!("unexpected error {:?}", err);
+        };
+        assert!(matches!(
+            decode_err.downcast_ref::<DecodeError>(),
+            Some(DecodeError::UnknownFormat)
+        ));
+    }
+}
@@ -9,6 +9,9 @@ use wasm_bindgen::prelude::*;
 extern "C" {
     #[wasm_bindgen(js_namespace = console, js_name = log)]
     pub fn js_console_log(s: &str);
+
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    pub fn js_performance_now() -> f64;
 }
 
 #[cfg(target_arch = "wasm32")]
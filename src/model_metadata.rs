@@ -0,0 +1,124 @@
+//! Properties of OCR models which are embedded in `.rten` model files.
+//!
+//! The metadata block of a `.rten` file has a fixed set of string fields
+//! (see [rten::ModelMetadata]). ocrs models store additional properties in
+//! the `description` field, as lines of the form `ocrs.<key>=<value>`. Lines
+//! which do not have this form are ordinary description text and are ignored.
+//!
+//! The supported keys are:
+//!
+//! - `ocrs.alphabet` - The characters which the recognition model's output
+//!   classes correspond to, excluding the CTC blank class. The value extends
+//!   to the end of the line and is not trimmed, since the alphabet may start
+//!   or end with a space.
+//! - `ocrs.input_height` - The height of line images expected by the
+//!   recognition model. This is only needed for models whose input height is
+//!   a symbolic dimension.
+
+use rten::Model;
+
+use crate::errors::{ModelKind, OcrError};
+
+/// Prefix of lines in a model description which specify properties.
+const PROPERTY_PREFIX: &str = "ocrs.";
+
+/// Properties of an OCR model read from its embedded metadata.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelProperties {
+    /// Alphabet used by a recognition model.
+    pub alphabet: Option<String>,
+
+    /// Height of input images expected by a recognition model.
+    pub input_height: Option<u32>,
+}
+
+impl ModelProperties {
+    /// Read properties from the metadata embedded in a model of type `kind`.
+    pub fn from_model(model: &Model, kind: ModelKind) -> Result<ModelProperties, OcrError> {
+        model
+            .metadata()
+            .description()
+            .map(Self::parse)
+            .unwrap_or(Ok(ModelProperties::default()))
+            .map_err(|err| OcrError::InvalidModel(kind, err))
+    }
+
+    /// Parse properties from a model description.
+    ///
+    /// Unknown `ocrs.` keys are ignored, so that newer models can be used
+    /// with older versions of this library.
+    pub fn parse(description: &str) -> Result<ModelProperties, String> {
+        let mut props = ModelProperties::default();
+
+        for line in description.lines() {
+            let Some((key, value)) = line
+                .strip_prefix(PROPERTY_PREFIX)
+                .and_then(|prop| prop.split_once('='))
+            else {
+                continue;
+            };
+
+            match key {
+                "alphabet" => props.alphabet = Some(value.to_string()),
+                "input_height" => {
+                    let height = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("invalid model input height \"{}\"", value))?;
+                    props.input_height = Some(height);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(props)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModelProperties;
+
+    #[test]
+    fn test_parse_model_properties() {
+        struct Case<'a> {
+            description: &'a str,
+            expected: Option<ModelProperties>,
+        }
+
+        let cases = [
+            // Plain description without properties.
+            Case {
+                description: "Text recognition model",
+                expected: Some(ModelProperties::default()),
+            },
+            // Alphabet with leading space, mixed with description text.
+            Case {
+                description: "Text recognition model\nocrs.alphabet= 0123\nocrs.input_height=64",
+                expected: Some(ModelProperties {
+                    alphabet: Some(" 0123".to_string()),
+                    input_height: Some(64),
+                }),
+            },
+            // Unknown keys are ignored.
+            Case {
+                description: "ocrs.future_key=abc",
+                expected: Some(ModelProperties::default()),
+            },
+            // Invalid values are an error.
+            Case {
+                description: "ocrs.input_height=tall",
+                expected: None,
+            },
+        ];
+
+        for Case {
+            description,
+            expected,
+        } in cases
+        {
+            let props = ModelProperties::parse(description);
+            assert_eq!(props.ok(), expected);
+        }
+    }
+}
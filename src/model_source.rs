@@ -0,0 +1,127 @@
+//! Deferred loading of models from files or in-memory buffers.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use rten::Model;
+
+use crate::errors::OcrError;
+
+/// Environment variable which overrides the default model directory.
+pub const MODEL_DIR_ENV_VAR: &str = "OCRS_MODEL_DIR";
+
+/// File name of the text detection model in the default model directory.
+pub const DETECTION_MODEL_FILE: &str = "text-detection.rten";
+
+/// File name of the text recognition model in the default model directory.
+pub const RECOGNITION_MODEL_FILE: &str = "text-recognition.rten";
+
+/// Location of a serialized `.rten` model which has not been loaded yet.
+///
+/// Sources are loaded when the [OcrEngine](crate::OcrEngine) is constructed,
+/// so that all model loading errors are reported from one place.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModelSource {
+    /// Path to a `.rten` file.
+    Path(PathBuf),
+
+    /// Contents of a `.rten` file.
+    Bytes(Vec<u8>),
+}
+
+impl ModelSource {
+    /// Load the model.
+    ///
+    /// If `memory_map` is true, models loaded from a path are memory-mapped
+    /// rather than read into memory. This has no effect for [ModelSource::Bytes].
+    pub fn load(self, memory_map: bool) -> Result<Model, OcrError> {
+        match self {
+            ModelSource::Path(path) => {
+                load_file(&path, memory_map).map_err(|error| OcrError::ModelLoadFailed {
+                    path: Some(path),
+                    error,
+                })
+            }
+            ModelSource::Bytes(data) => {
+                Model::load(data).map_err(|error| OcrError::ModelLoadFailed { path: None, error })
+            }
+        }
+    }
+}
+
+impl From<PathBuf> for ModelSource {
+    fn from(path: PathBuf) -> Self {
+        ModelSource::Path(path)
+    }
+}
+
+impl From<&Path> for ModelSource {
+    fn from(path: &Path) -> Self {
+        ModelSource::Path(path.to_path_buf())
+    }
+}
+
+impl From<Vec<u8>> for ModelSource {
+    fn from(data: Vec<u8>) -> Self {
+        ModelSource::Bytes(data)
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn load_file(path: &Path, memory_map: bool) -> Result<Model, rten::ModelLoadError> {
+    if memory_map {
+        // Safety: The caller opted into memory-mapping, and is responsible for
+        // ensuring the file is not modified while the model is alive.
+        unsafe { Model::load_mmap(path) }
+    } else {
+        Model::load_file(path)
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+fn load_file(path: &Path, _memory_map: bool) -> Result<Model, rten::ModelLoadError> {
+    Model::load_file(path)
+}
+
+/// Return the directory which is searched for models by
+/// [OcrEngineParams::with_default_models](crate::OcrEngineParams::with_default_models).
+///
+/// This is the value of the `OCRS_MODEL_DIR` environment variable if set,
+/// otherwise `.cache/ocrs` in the user's home directory. This is the same
+/// location that the `ocrs` CLI tool downloads models to.
+///
+/// The home directory is read from `HOME`, or from `USERPROFILE` on Windows.
+/// Returns `None` if none of these variables are set.
+pub fn default_model_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(MODEL_DIR_ENV_VAR) {
+        return Some(PathBuf::from(dir));
+    }
+    home_dir().map(|home| home.join(".cache").join("ocrs"))
+}
+
+/// Return the user's home directory.
+fn home_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME");
+    #[cfg(windows)]
+    let home = home.or_else(|| env::var_os("USERPROFILE"));
+    home.map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::ModelSource;
+
+    #[test]
+    fn test_model_source_load_errors() {
+        let err = ModelSource::Path(PathBuf::from("does-not-exist.rten"))
+            .load(false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("does-not-exist.rten"));
+
+        let err = ModelSource::Bytes(vec![1, 2, 3]).load(false).err();
+        assert!(err.is_some());
+    }
+}
@@ -0,0 +1,101 @@
+//! Orientation metadata of image files.
+
+use rten_imageproc::Rect;
+
+/// Transform which is applied to the stored pixels of an image to display it
+/// upright, as specified by the EXIF or TIFF orientation tag.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// The stored image is upright.
+    #[default]
+    Normal,
+
+    /// Flip horizontally.
+    FlipHorizontal,
+
+    /// Rotate by 180 degrees.
+    Rotate180,
+
+    /// Flip vertically.
+    FlipVertical,
+
+    /// Rotate by 90 degrees clockwise, then flip horizontally.
+    Rotate90FlipHorizontal,
+
+    /// Rotate by 90 degrees clockwise.
+    Rotate90,
+
+    /// Rotate by 270 degrees clockwise, then flip horizontally.
+    Rotate270FlipHorizontal,
+
+    /// Rotate by 270 degrees clockwise.
+    Rotate270,
+}
+
+impl Orientation {
+    /// Convert an EXIF orientation value (1-8) to an orientation.
+    pub fn from_exif(value: u8) -> Option<Orientation> {
+        let orientation = match value {
+            1 => Orientation::Normal,
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Rotate90FlipHorizontal,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Rotate270FlipHorizontal,
+            8 => Orientation::Rotate270,
+            _ => return None,
+        };
+        Some(orientation)
+    }
+
+    /// Return the EXIF orientation value (1-8) for this orientation.
+    pub fn to_exif(self) -> u8 {
+        match self {
+            Orientation::Normal => 1,
+            Orientation::FlipHorizontal => 2,
+            Orientation::Rotate180 => 3,
+            Orientation::FlipVertical => 4,
+            Orientation::Rotate90FlipHorizontal => 5,
+            Orientation::Rotate90 => 6,
+            Orientation::Rotate270FlipHorizontal => 7,
+            Orientation::Rotate270 => 8,
+        }
+    }
+
+    /// Return true if the transform swaps the width and height of the image.
+    pub fn swaps_dimensions(self) -> bool {
+        self.to_exif() >= 5
+    }
+
+    /// Map a point in the upright image, whose size is `(width, height)`,
+    /// to the corresponding point in the stored image.
+    ///
+    /// Coordinates refer to pixel edges rather than centers, so that
+    /// `(0, 0)` and `(width, height)` are the corners of the image.
+    pub fn to_raw_point(self, x: i32, y: i32, width: i32, height: i32) -> (i32, i32) {
+        match self {
+            Orientation::Normal => (x, y),
+            Orientation::FlipHorizontal => (width - x, y),
+            Orientation::Rotate180 => (width - x, height - y),
+            Orientation::FlipVertical => (x, height - y),
+            Orientation::Rotate90FlipHorizontal => (y, x),
+            Orientation::Rotate90 => (y, width - x),
+            Orientation::Rotate270FlipHorizontal => (height - y, width - x),
+            Orientation::Rotate270 => (height - y, x),
+        }
+    }
+
+    /// Map a rectangle in the upright image, whose size is `(width, height)`,
+    /// to the corresponding rectangle in the stored image.
+    ///
+    /// This can be used to map the coordinates of recognized text, such as
+    /// [TextChar::rect](crate::TextChar::rect), back to the pixel grid of the
+    /// original file.
+    pub fn to_raw_rect(self, rect: Rect, width: u32, height: u32) -> Rect {
+        let (width, height) = (width as i32, height as i32);
+        let (x0, y0) = self.to_raw_point(rect.left(), rect.top(), width, height);
+        let (x1, y1) = self.to_raw_point(rect.right(), rect.bottom(), width, height);
+        Rect::from_tlbr(y0.min(y1), x0.min(x1), y0.max(y1), x0.max(x1))
+    }
+}
@@ -1,16 +1,31 @@
 use std::fmt::Debug;
 
+use rayon::prelude::*;
+use rten_imageproc::Rect;
+use rten_tensor::errors::FromDataError;
 use rten_tensor::prelude::*;
 use rten_tensor::{NdTensor, NdTensorView};
 use thiserror::Error;
 
 /// View of an image's pixels, in either (height, width, channels) or (channels,
 /// height, width) order.
+#[non_exhaustive]
 pub enum ImagePixels<'a> {
     /// Pixel values in the range [0, 1]
     Floats(NdTensorView<'a, f32, 3>),
     /// Pixel values in the range [0, 255]
     Bytes(NdTensorView<'a, u8, 3>),
+    /// Pixel values in the range [0, 65535]
+    Shorts(NdTensorView<'a, u16, 3>),
+    /// Planar YUV 4:2:0 pixels, as produced by video capture devices.
+    ///
+    /// The Y plane has the full image size. The U and V planes have half the
+    /// width and height, rounded up.
+    Yuv420 {
+        y: NdTensorView<'a, u8, 2>,
+        u: NdTensorView<'a, u8, 2>,
+        v: NdTensorView<'a, u8, 2>,
+    },
 }
 
 impl<'a> From<NdTensorView<'a, f32, 3>> for ImagePixels<'a> {
@@ -25,32 +40,87 @@ impl<'a> From<NdTensorView<'a, u8, 3>> for ImagePixels<'a> {
     }
 }
 
-impl ImagePixels<'_> {
-    fn shape(&self) -> [usize; 3] {
-        match self {
-            ImagePixels::Floats(f) => f.shape(),
-            ImagePixels::Bytes(b) => b.shape(),
+impl<'a> From<NdTensorView<'a, u16, 3>> for ImagePixels<'a> {
+    fn from(value: NdTensorView<'a, u16, 3>) -> Self {
+        ImagePixels::Shorts(value)
+    }
+}
+
+impl<'a> ImagePixels<'a> {
+    /// Return a view of the region `[y0, y1) x [x0, x1)` of the image, where
+    /// `order` specifies the order of dimensions.
+    fn region(
+        &self,
+        order: DimOrder,
+        y0: usize,
+        y1: usize,
+        x0: usize,
+        x1: usize,
+    ) -> ImagePixels<'a> {
+        match (self, order) {
+            (ImagePixels::Floats(f), DimOrder::Hwc) => {
+                ImagePixels::Floats(f.slice((y0..y1, x0..x1)))
+            }
+            (ImagePixels::Floats(f), DimOrder::Chw) => {
+                ImagePixels::Floats(f.slice((.., y0..y1, x0..x1)))
+            }
+            (ImagePixels::Bytes(b), DimOrder::Hwc) => ImagePixels::Bytes(b.slice((y0..y1, x0..x1))),
+            (ImagePixels::Bytes(b), DimOrder::Chw) => {
+                ImagePixels::Bytes(b.slice((.., y0..y1, x0..x1)))
+            }
+            (ImagePixels::Shorts(s), DimOrder::Hwc) => {
+                ImagePixels::Shorts(s.slice((y0..y1, x0..x1)))
+            }
+            (ImagePixels::Shorts(s), DimOrder::Chw) => {
+                ImagePixels::Shorts(s.slice((.., y0..y1, x0..x1)))
+            }
+            (ImagePixels::Yuv420 { y, u, v }, _) => {
+                // The region's origin is even, so it is aligned with the
+                // subsampled chroma planes.
+                let (cy, cx) = (y0 / 2..y1.div_ceil(2), x0 / 2..x1.div_ceil(2));
+                ImagePixels::Yuv420 {
+                    y: y.slice((y0..y1, x0..x1)),
+                    u: u.slice((cy.clone(), cx.clone())),
+                    v: v.slice((cy, cx)),
+                }
+            }
         }
     }
 
-    /// Return the pixel value at an index as a value in [0, 1].
-    fn pixel_as_f32(&self, index: [usize; 3]) -> f32 {
+    fn shape(&self) -> [usize; 3] {
         match self {
-            ImagePixels::Floats(f) => f[index],
-            ImagePixels::Bytes(b) => b[index] as f32 / 255.,
+            ImagePixels::Floats(f) => f.shape(),
+            ImagePixels::Bytes(b) => b.shape(),
+            ImagePixels::Shorts(s) => s.shape(),
+            ImagePixels::Yuv420 { y, .. } => [3, y.size(0), y.size(1)],
         }
     }
 }
 
 /// Errors that can occur when creating an [ImageSource].
 #[derive(Error, Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum ImageSourceError {
-    /// The image channel count is not 1 (greyscale), 3 (RGB) or 4 (RGBA).
-    #[error("channel count is not 1, 3 or 4")]
+    /// The image channel count is not 1 (greyscale), 2 (greyscale + alpha),
+    /// 3 (RGB) or 4 (RGBA).
+    #[error("channel count is not 1, 2, 3 or 4")]
     UnsupportedChannelCount,
     /// The image data length is not a multiple of the channel size.
     #[error("data length is not a multiple of `width * height`")]
     InvalidDataLength,
+    /// The image channel count does not match the requested [ChannelOrder].
+    #[error("channel count does not match channel order")]
+    ChannelOrderMismatch,
+    /// The row stride is smaller than the length of a row of pixels.
+    #[error("row stride is less than `width * channels`")]
+    InvalidStride,
+    /// A region of the image is not contained within the image, or is not
+    /// aligned with the chroma planes of a YUV image.
+    #[error("region is outside the image or misaligned")]
+    InvalidRegion,
+    /// The image width or height is zero.
+    #[error("image width or height is zero")]
+    EmptyImage,
 }
 
 /// Specifies the order in which pixels are laid out in an image tensor.
@@ -64,11 +134,94 @@ pub enum DimOrder {
     Chw,
 }
 
+/// Specifies the meaning of each channel in an image.
+///
+/// Images with an alpha channel are composited onto a white background.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChannelOrder {
+    /// Greyscale.
+    Grey,
+    /// Greyscale with alpha.
+    GreyAlpha,
+    /// Red, green, blue.
+    Rgb,
+    /// Red, green, blue, alpha.
+    Rgba,
+    /// Blue, green, red. This is the order used by OpenCV and many video
+    /// capture APIs.
+    Bgr,
+    /// Blue, green, red, alpha.
+    Bgra,
+    /// Luma and chroma, using the "limited" range of luma values (16-235)
+    /// that is standard for video. This is used by images created with
+    /// [ImageSource::from_yuv420].
+    Yuv,
+    /// Luma and chroma, using the full range of luma values (0-255). This is
+    /// used by JPEG images and some camera APIs. Set it on images created
+    /// with [ImageSource::from_yuv420] using
+    /// [ImageSource::with_channel_order].
+    YuvFullRange,
+}
+
+impl ChannelOrder {
+    /// Return the weight of each channel when converting to luminance, and
+    /// the index of the alpha channel, if any. The weight of the alpha
+    /// channel is zero.
+    ///
+    /// Returns `None` for YUV channel orders, whose first channel is
+    /// luminance with a different value range.
+    fn luminance_weights(self) -> Option<([f32; 4], Option<usize>)> {
+        // ITU BT.601 weights for RGB => luminance conversion. These match what
+        // torchvision uses. See also https://stackoverflow.com/a/596241/434243.
+        let weights = match self {
+            ChannelOrder::Grey => ([1., 0., 0., 0.], None),
+            ChannelOrder::GreyAlpha => ([1., 0., 0., 0.], Some(1)),
+            ChannelOrder::Rgb => ([0.299, 0.587, 0.114, 0.], None),
+            ChannelOrder::Rgba => ([0.299, 0.587, 0.114, 0.], Some(3)),
+            ChannelOrder::Bgr => ([0.114, 0.587, 0.299, 0.], None),
+            ChannelOrder::Bgra => ([0.114, 0.587, 0.299, 0.], Some(3)),
+            ChannelOrder::Yuv | ChannelOrder::YuvFullRange => return None,
+        };
+        Some(weights)
+    }
+
+    /// Return the number of channels in images with this channel order.
+    pub fn channels(self) -> usize {
+        match self {
+            ChannelOrder::Grey => 1,
+            ChannelOrder::GreyAlpha => 2,
+            ChannelOrder::Rgb
+            | ChannelOrder::Bgr
+            | ChannelOrder::Yuv
+            | ChannelOrder::YuvFullRange => 3,
+            ChannelOrder::Rgba | ChannelOrder::Bgra => 4,
+        }
+    }
+
+    /// Return true if this is one of the YUV channel orders.
+    fn is_yuv(self) -> bool {
+        matches!(self, ChannelOrder::Yuv | ChannelOrder::YuvFullRange)
+    }
+
+    /// Return the default channel order for images with a given number of
+    /// channels.
+    fn for_channels(channels: usize) -> Option<ChannelOrder> {
+        match channels {
+            1 => Some(ChannelOrder::Grey),
+            2 => Some(ChannelOrder::GreyAlpha),
+            3 => Some(ChannelOrder::Rgb),
+            4 => Some(ChannelOrder::Rgba),
+            _ => None,
+        }
+    }
+}
+
 /// View of an image, for use with
 /// [OcrEngine::prepare_input](crate::OcrEngine::prepare_input).
 pub struct ImageSource<'a> {
     data: ImagePixels<'a>,
     order: DimOrder,
+    channel_order: ChannelOrder,
 }
 
 impl<'a> ImageSource<'a> {
@@ -86,6 +239,11 @@ impl<'a> ImageSource<'a> {
     /// # Ok(())
     /// # }
     /// ```
+    ///
+    /// The pixels are used as-is, so EXIF orientation metadata in the source
+    /// file must be applied by the caller. If the `decode` feature is enabled,
+    /// `decode::decode_pages` does this and reports the transform that was
+    /// applied.
     pub fn from_bytes(
         bytes: &'a [u8],
         dimensions: (u32, u32),
@@ -108,8 +266,46 @@ impl<'a> ImageSource<'a> {
         )
     }
 
-    /// Create an image source from a tensor of bytes (`u8`) or floats (`f32`),
-    /// in either channels-first (CHW) or channels-last (HWC) order.
+    /// Create an image source from a buffer of pixels in HWC order, where
+    /// consecutive rows start `row_stride` elements apart.
+    ///
+    /// This is useful for camera frames and GPU readbacks whose rows are
+    /// padded. Pixels are read in place, without copying the buffer.
+    pub fn from_strided_bytes(
+        bytes: &'a [u8],
+        dimensions: (u32, u32),
+        channels: usize,
+        row_stride: usize,
+    ) -> Result<ImageSource<'a>, ImageSourceError> {
+        let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+        if width == 0 || height == 0 {
+            return Err(ImageSourceError::EmptyImage);
+        }
+        if row_stride < width * channels {
+            return Err(ImageSourceError::InvalidStride);
+        }
+
+        let view = NdTensorView::from_data_with_strides(
+            [height, width, channels],
+            bytes,
+            [row_stride, channels, 1],
+        )
+        .map_err(|err| match err {
+            FromDataError::StorageTooShort => ImageSourceError::InvalidDataLength,
+            _ => ImageSourceError::InvalidStride,
+        })?;
+        Self::from_tensor(view, DimOrder::Hwc)
+    }
+
+    /// Create an image source from a tensor of bytes (`u8`), 16-bit values
+    /// (`u16`) or floats (`f32`), in either channels-first (CHW) or
+    /// channels-last (HWC) order.
+    ///
+    /// The tensor does not need to be contiguous. For example it can be a
+    /// view with padded rows, or a slice of a larger image.
+    ///
+    /// The channel order is inferred from the number of channels. Use
+    /// [ImageSource::with_channel_order] to change it.
     pub fn from_tensor<T>(
         data: NdTensorView<'a, T, 3>,
         order: DimOrder,
@@ -121,13 +317,101 @@ impl<'a> ImageSource<'a> {
             DimOrder::Hwc => data.size(2),
             DimOrder::Chw => data.size(0),
         };
-        match channels {
-            1 | 3 | 4 => Ok(ImageSource {
-                data: data.into(),
-                order,
-            }),
-            _ => Err(ImageSourceError::UnsupportedChannelCount),
+        let channel_order = ChannelOrder::for_channels(channels)
+            .ok_or(ImageSourceError::UnsupportedChannelCount)?;
+        Ok(ImageSource {
+            data: data.into(),
+            order,
+            channel_order,
+        })
+    }
+
+    /// Create an image source from a planar YUV 4:2:0 (I420) frame.
+    ///
+    /// `data` contains the Y plane, followed by the U and V planes, each of
+    /// which has half the width and height of the image, rounded up. Luma
+    /// values are assumed to use the limited video range. Use
+    /// [ChannelOrder::YuvFullRange] for frames which use the full range.
+    pub fn from_yuv420(
+        data: &'a [u8],
+        dimensions: (u32, u32),
+    ) -> Result<ImageSource<'a>, ImageSourceError> {
+        let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+        if width == 0 || height == 0 {
+            return Err(ImageSourceError::EmptyImage);
+        }
+
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let luma_len = width * height;
+        let chroma_len = chroma_width * chroma_height;
+        if data.len() != luma_len + 2 * chroma_len {
+            return Err(ImageSourceError::InvalidDataLength);
+        }
+
+        let (y, chroma) = data.split_at(luma_len);
+        let (u, v) = chroma.split_at(chroma_len);
+        Ok(ImageSource {
+            data: ImagePixels::Yuv420 {
+                y: NdTensorView::from_data([height, width], y),
+                u: NdTensorView::from_data([chroma_height, chroma_width], u),
+                v: NdTensorView::from_data([chroma_height, chroma_width], v),
+            },
+            order: DimOrder::Chw,
+            channel_order: ChannelOrder::Yuv,
+        })
+    }
+
+    /// Set the meaning of each channel in the image.
+    ///
+    /// This is needed for images in BGR or BGRA order, and for YUV images
+    /// which use the full range of luma values. Returns an error if
+    /// the number of channels in the image does not match `channel_order`.
+    pub fn with_channel_order(
+        self,
+        channel_order: ChannelOrder,
+    ) -> Result<ImageSource<'a>, ImageSourceError> {
+        let yuv_data = matches!(self.data, ImagePixels::Yuv420 { .. });
+        if self.shape()[0] != channel_order.channels() || yuv_data != channel_order.is_yuv() {
+            return Err(ImageSourceError::ChannelOrderMismatch);
+        }
+        Ok(ImageSource {
+            channel_order,
+            ..self
+        })
+    }
+
+    /// Return the meaning of each channel in the image.
+    pub fn channel_order(&self) -> ChannelOrder {
+        self.channel_order
+    }
+
+    /// Return a view of a rectangular region of the image, without copying
+    /// the pixels.
+    ///
+    /// Coordinates of text found in the region are relative to the region's
+    /// top-left corner. For YUV images, the top and left coordinates of the
+    /// region must be even.
+    pub fn region(&self, rect: Rect) -> Result<ImageSource<'a>, ImageSourceError> {
+        let [_, height, width] = self.shape();
+        if rect.left() < 0
+            || rect.top() < 0
+            || rect.right() > width as i32
+            || rect.bottom() > height as i32
+            || rect.is_empty()
+        {
+            return Err(ImageSourceError::InvalidRegion);
         }
+        if self.channel_order.is_yuv() && (rect.left() % 2 != 0 || rect.top() % 2 != 0) {
+            return Err(ImageSourceError::InvalidRegion);
+        }
+
+        let (y0, y1) = (rect.top() as usize, rect.bottom() as usize);
+        let (x0, x1) = (rect.left() as usize, rect.right() as usize);
+        Ok(ImageSource {
+            data: self.data.region(self.order, y0, y1, x0, x1),
+            order: self.order,
+            channel_order: self.channel_order,
+        })
     }
 
     /// Return the shape of the image as a `[channels, height, width]` array.
@@ -139,16 +423,6 @@ impl<'a> ImageSource<'a> {
             DimOrder::Hwc => [shape[2], shape[0], shape[1]],
         }
     }
-
-    /// Return the pixel from a given channel and spatial coordinate, as a
-    /// float in [0, 1].
-    pub(crate) fn get_pixel(&self, channel: usize, y: usize, x: usize) -> f32 {
-        let index = match self.order {
-            DimOrder::Chw => [channel, y, x],
-            DimOrder::Hwc => [y, x, channel],
-        };
-        self.data.pixel_as_f32(index)
-    }
 }
 
 /// The value used to represent fully black pixels in OCR input images
@@ -160,7 +434,8 @@ pub const BLACK_VALUE: f32 = -0.5;
 /// This involves:
 ///
 /// - Converting the pixels to floats
-/// - Converting the color format to greyscale
+/// - Converting the color format to greyscale, compositing transparent pixels
+///   onto a white background
 /// - Adding a bias ([BLACK_VALUE]) to the greyscale value
 ///
 /// The greyscale conversion is intended to approximately match torchvision's
@@ -168,43 +443,261 @@ pub const BLACK_VALUE: f32 = -0.5;
 /// ImageReadMode.GRAY)`, which is used when training models with greyscale
 /// inputs. torchvision internally uses libpng's `png_set_rgb_to_gray`.
 pub fn prepare_image(img: ImageSource) -> NdTensor<f32, 3> {
-    let [chans, height, width] = img.shape();
-    assert!(
-        matches!(chans, 1 | 3 | 4),
-        "expected greyscale, RGB or RGBA input image"
-    );
-
-    let used_chans = chans.min(3); // For RGBA images, only RGB channels are used
-    let chan_weights: &[f32] = if chans == 1 {
-        &[1.]
-    } else {
-        // ITU BT.601 weights for RGB => luminance conversion. These match what
-        // torchvision uses. See also https://stackoverflow.com/a/596241/434243.
-        &[0.299, 0.587, 0.114]
+    let [_chans, height, width] = img.shape();
+
+    let mut grey_img = NdTensor::zeros([height, width]);
+    if width > 0 {
+        let rows = grey_img.data_mut().expect("tensor should be contiguous");
+        match &img.data {
+            ImagePixels::Floats(f) => convert_rows(f.view(), img.order, img.channel_order, rows),
+            ImagePixels::Bytes(b) => convert_rows(b.view(), img.order, img.channel_order, rows),
+            ImagePixels::Shorts(s) => convert_rows(s.view(), img.order, img.channel_order, rows),
+            ImagePixels::Yuv420 { y, .. } => {
+                let full_range = img.channel_order == ChannelOrder::YuvFullRange;
+                rows.par_chunks_mut(width)
+                    .enumerate()
+                    .for_each(|(row, dst)| convert_luma_row(y.slice(row), full_range, dst));
+            }
+        }
+    }
+    grey_img.into_shape([1, height, width])
+}
+
+/// Numeric type of pixel values in an [ImagePixels] tensor.
+trait PixelValue: Copy + Send + Sync {
+    /// Factor which scales values to the range [0, 1].
+    const SCALE: f32;
+
+    fn to_f32(self) -> f32;
+}
+
+impl PixelValue for u8 {
+    const SCALE: f32 = 1. / 255.;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+impl PixelValue for u16 {
+    const SCALE: f32 = 1. / 65535.;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+impl PixelValue for f32 {
+    const SCALE: f32 = 1.;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+/// Convert the pixels of `src` to greyscale and write them to `dst`, which
+/// holds the rows of the output image. Rows are processed in parallel.
+fn convert_rows<T: PixelValue>(
+    src: NdTensorView<T, 3>,
+    order: DimOrder,
+    channel_order: ChannelOrder,
+    dst: &mut [f32],
+) {
+    let (weights, alpha) = channel_order
+        .luminance_weights()
+        .expect("channel order should not be YUV");
+    let width = match order {
+        DimOrder::Hwc => src.size(1),
+        DimOrder::Chw => src.size(2),
     };
+    dst.par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, dst)| match order {
+            DimOrder::Hwc => convert_hwc_row(src.slice(y), weights, alpha, dst),
+            DimOrder::Chw => convert_chw_row(src.slice((.., y)), weights, alpha, dst),
+        });
+}
 
-    // Ideally we would use `NdTensor::from_fn` here, but explicit loops are
-    // currently faster.
-    let mut grey_img = NdTensor::uninit([height, width]);
-    for y in 0..height {
-        for x in 0..width {
-            let mut pixel = BLACK_VALUE;
-            for (chan, weight) in (0..used_chans).zip(chan_weights) {
-                pixel += img.get_pixel(chan, y, x) * weight
+/// Convert a `[width, channels]` row of pixels to greyscale.
+fn convert_hwc_row<T: PixelValue>(
+    src: NdTensorView<T, 2>,
+    weights: [f32; 4],
+    alpha: Option<usize>,
+    dst: &mut [f32],
+) {
+    // Use a specialized loop for each channel count if the row is contiguous.
+    match (src.data(), src.size(1)) {
+        (Some(data), 1) => convert_pixels::<T, 1>(data, weights, alpha, dst),
+        (Some(data), 2) => convert_pixels::<T, 2>(data, weights, alpha, dst),
+        (Some(data), 3) => convert_pixels::<T, 3>(data, weights, alpha, dst),
+        (Some(data), 4) => convert_pixels::<T, 4>(data, weights, alpha, dst),
+        _ => {
+            for (x, out) in dst.iter_mut().enumerate() {
+                let pixel = src.slice::<1, _>(x);
+                let mut luminance = 0.;
+                for (value, weight) in pixel.iter().zip(weights) {
+                    luminance += value.to_f32() * weight;
+                }
+                let alpha = alpha.map(|chan| pixel[[chan]].to_f32() * T::SCALE);
+                *out = BLACK_VALUE + composite(luminance * T::SCALE, alpha);
             }
-            grey_img[[y, x]].write(pixel);
         }
     }
-    // Safety: We initialized all the pixels.
-    unsafe { grey_img.assume_init().into_shape([1, height, width]) }
+}
+
+/// Convert contiguous pixels with `C` channels to greyscale.
+fn convert_pixels<T: PixelValue, const C: usize>(
+    src: &[T],
+    weights: [f32; 4],
+    alpha: Option<usize>,
+    dst: &mut [f32],
+) {
+    for (out, pixel) in dst.iter_mut().zip(src.chunks_exact(C)) {
+        let mut luminance = 0.;
+        for chan in 0..C {
+            luminance += pixel[chan].to_f32() * weights[chan];
+        }
+        let alpha = alpha.map(|chan| pixel[chan].to_f32() * T::SCALE);
+        *out = BLACK_VALUE + composite(luminance * T::SCALE, alpha);
+    }
+}
+
+/// Convert a `[channels, width]` row of pixels to greyscale.
+fn convert_chw_row<T: PixelValue>(
+    src: NdTensorView<T, 2>,
+    weights: [f32; 4],
+    alpha: Option<usize>,
+    dst: &mut [f32],
+) {
+    // Accumulate one channel at a time, so that the inner loops operate on
+    // contiguous rows where possible.
+    let add_channel = |dst: &mut [f32], chan: usize, weight: f32| {
+        let src = src.slice::<1, _>(chan);
+        let weight = weight * T::SCALE;
+        match src.data() {
+            Some(data) => {
+                for (out, value) in dst.iter_mut().zip(data) {
+                    *out += value.to_f32() * weight;
+                }
+            }
+            None => {
+                for (out, value) in dst.iter_mut().zip(src.iter()) {
+                    *out += value.to_f32() * weight;
+                }
+            }
+        }
+    };
+
+    dst.fill(0.);
+    for (chan, weight) in weights.into_iter().enumerate().take(src.size(0)) {
+        if weight != 0. {
+            add_channel(dst, chan, weight);
+        }
+    }
+
+    if let Some(alpha_chan) = alpha {
+        let alpha_row = src.slice::<1, _>(alpha_chan);
+        for (out, alpha) in dst.iter_mut().zip(alpha_row.iter()) {
+            *out = composite(*out, Some(alpha.to_f32() * T::SCALE));
+        }
+    }
+    for out in dst.iter_mut() {
+        *out += BLACK_VALUE;
+    }
+}
+
+/// Convert a row of the Y plane of a YUV image to greyscale.
+fn convert_luma_row(src: NdTensorView<u8, 1>, full_range: bool, dst: &mut [f32]) {
+    // The Y channel is the BT.601 luminance, scaled to 16-235 for limited
+    // range video or 0-255 for full range.
+    let (offset, scale) = if full_range { (0., 255.) } else { (16., 219.) };
+    for (out, value) in dst.iter_mut().zip(src.iter()) {
+        let luminance = (*value as f32 - offset) / scale;
+        *out = BLACK_VALUE + luminance.clamp(0., 1.);
+    }
+}
+
+/// Composite a pixel with luminance and alpha in [0, 1] onto a white
+/// background.
+fn composite(luminance: f32, alpha: Option<f32>) -> f32 {
+    match alpha {
+        Some(alpha) => luminance * alpha + (1. - alpha),
+        None => luminance,
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use rten_imageproc::Rect;
     use rten_tensor::prelude::*;
-    use rten_tensor::NdTensor;
+    use rten_tensor::{NdTensor, NdTensorView, SliceRange};
 
-    use super::{DimOrder, ImageSource, ImageSourceError};
+    use super::{
+        prepare_image, ChannelOrder, DimOrder, ImagePixels, ImageSource, ImageSourceError,
+        BLACK_VALUE,
+    };
+
+    /// Return the pixel from a given channel and spatial coordinate, as a
+    /// float in [0, 1].
+    fn get_pixel(img: &ImageSource, channel: usize, y: usize, x: usize) -> f32 {
+        let index = match img.order {
+            DimOrder::Chw => [channel, y, x],
+            DimOrder::Hwc => [y, x, channel],
+        };
+        match &img.data {
+            ImagePixels::Floats(f) => f[index],
+            ImagePixels::Bytes(b) => b[index] as f32 / 255.,
+            ImagePixels::Shorts(s) => s[index] as f32 / 65535.,
+            ImagePixels::Yuv420 { y, u, v } => {
+                let [chan, row, col] = index;
+                let value = match chan {
+                    0 => y[[row, col]],
+                    1 => u[[row / 2, col / 2]],
+                    _ => v[[row / 2, col / 2]],
+                };
+                value as f32 / 255.
+            }
+        }
+    }
+
+    /// Reference implementation of [prepare_image], which converts one pixel
+    /// at a time.
+    fn reference_prepare_image(img: &ImageSource) -> NdTensor<f32, 3> {
+        const RGB_WEIGHTS: [f32; 3] = [0.299, 0.587, 0.114];
+        const BGR_WEIGHTS: [f32; 3] = [0.114, 0.587, 0.299];
+
+        let [_, height, width] = img.shape();
+        NdTensor::from_fn([1, height, width], |[_, y, x]| {
+            let weighted_sum = |weights: [f32; 3]| -> f32 {
+                (0..3)
+                    .zip(weights)
+                    .map(|(chan, weight)| get_pixel(img, chan, y, x) * weight)
+                    .sum()
+            };
+            let luma = |offset: f32, scale: f32| {
+                ((get_pixel(img, 0, y, x) * 255. - offset) / scale).clamp(0., 1.)
+            };
+
+            let (luminance, alpha_chan) = match img.channel_order {
+                ChannelOrder::Grey => (get_pixel(img, 0, y, x), None),
+                ChannelOrder::GreyAlpha => (get_pixel(img, 0, y, x), Some(1)),
+                ChannelOrder::Rgb => (weighted_sum(RGB_WEIGHTS), None),
+                ChannelOrder::Rgba => (weighted_sum(RGB_WEIGHTS), Some(3)),
+                ChannelOrder::Bgr => (weighted_sum(BGR_WEIGHTS), None),
+                ChannelOrder::Bgra => (weighted_sum(BGR_WEIGHTS), Some(3)),
+                ChannelOrder::Yuv => (luma(16., 219.), None),
+                ChannelOrder::YuvFullRange => (luma(0., 255.), None),
+            };
+            let luminance = match alpha_chan {
+                Some(chan) => {
+                    let alpha = get_pixel(img, chan, y, x);
+                    luminance * alpha + (1. - alpha)
+                }
+                None => luminance,
+            };
+            BLACK_VALUE + luminance
+        })
+    }
 
     #[test]
     fn test_image_source_from_bytes() {
@@ -232,6 +725,12 @@ mod tests {
                 len: 8 * 8 * 2,
                 width: 8,
                 height: 8,
+                error: None,
+            },
+            Case {
+                len: 4 * 4 * 5,
+                width: 4,
+                height: 4,
                 error: Some(ImageSourceError::UnsupportedChannelCount),
             },
             Case {
@@ -259,7 +758,7 @@ mod tests {
                     NdTensor::from_data([height as usize, width as usize, channels], data.clone());
 
                 assert_eq!(source.shape(), tensor.permuted([2, 0, 1]).shape());
-                assert_eq!(source.get_pixel(0, 2, 3), tensor[[2, 3, 0]] as f32 / 255.,);
+                assert_eq!(get_pixel(&source, 0, 2, 3), tensor[[2, 3, 0]] as f32 / 255.,);
             }
         }
     }
@@ -310,7 +809,7 @@ mod tests {
                     }
                 );
                 assert_eq!(
-                    source.get_pixel(0, 2, 3),
+                    get_pixel(&source, 0, 2, 3),
                     match order {
                         DimOrder::Chw => tensor[[0, 2, 3]] as f32 / 255.,
                         DimOrder::Hwc => tensor[[2, 3, 0]] as f32 / 255.,
@@ -319,4 +818,249 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_prepare_image_matches_reference() {
+        let assert_matches = |source: ImageSource| {
+            let expected = reference_prepare_image(&source);
+            let actual = prepare_image(source);
+            assert_eq!(actual.shape(), expected.shape());
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                assert!((a - e).abs() < 1e-5, "{} != {}", a, e);
+            }
+        };
+
+        let (height, width) = (5, 7);
+        let orders = [
+            ChannelOrder::Grey,
+            ChannelOrder::GreyAlpha,
+            ChannelOrder::Rgb,
+            ChannelOrder::Rgba,
+            ChannelOrder::Bgr,
+            ChannelOrder::Bgra,
+        ];
+        for channel_order in orders {
+            let chans = channel_order.channels();
+            let bytes = NdTensor::from_fn([chans, height, width], |_| fastrand::u8(..));
+            let shorts = bytes.map(|x| *x as u16 * 257);
+            let floats = bytes.map(|x| *x as f32 / 255.);
+
+            // Images in CHW order, and views in HWC order whose pixels are
+            // not contiguous.
+            for source in [
+                ImageSource::from_tensor(bytes.view(), DimOrder::Chw),
+                ImageSource::from_tensor(bytes.permuted([1, 2, 0]), DimOrder::Hwc),
+                ImageSource::from_tensor(shorts.view(), DimOrder::Chw),
+                ImageSource::from_tensor(shorts.permuted([1, 2, 0]), DimOrder::Hwc),
+                ImageSource::from_tensor(floats.view(), DimOrder::Chw),
+                ImageSource::from_tensor(floats.permuted([1, 2, 0]), DimOrder::Hwc),
+            ] {
+                let source = source.unwrap().with_channel_order(channel_order).unwrap();
+                assert_matches(source);
+            }
+
+            // Contiguous image in HWC order.
+            let hwc_bytes = bytes.permuted([1, 2, 0]).to_tensor();
+            let source = ImageSource::from_tensor(hwc_bytes.view(), DimOrder::Hwc)
+                .unwrap()
+                .with_channel_order(channel_order)
+                .unwrap();
+            assert_matches(source);
+        }
+
+        let yuv: Vec<u8> = (0..width * height + 2 * 4 * 3)
+            .map(|_| fastrand::u8(..))
+            .collect();
+        for channel_order in [ChannelOrder::Yuv, ChannelOrder::YuvFullRange] {
+            let source = ImageSource::from_yuv420(&yuv, (width as u32, height as u32))
+                .unwrap()
+                .with_channel_order(channel_order)
+                .unwrap();
+            assert_matches(source);
+        }
+    }
+
+    #[test]
+    fn test_prepare_image_pixel_layouts() {
+        let prepare = |source: ImageSource| prepare_image(source).to_vec();
+        let assert_close = |actual: Vec<f32>, expected: &[f32]| {
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-3, "{:?} != {:?}", actual, expected);
+            }
+        };
+
+        // Two RGB pixels: red and white.
+        let rgb = [255u8, 0, 0, 255, 255, 255];
+        let expected = prepare(ImageSource::from_bytes(&rgb, (2, 1)).unwrap());
+        assert_close(expected.clone(), &[BLACK_VALUE + 0.299, BLACK_VALUE + 1.]);
+
+        // The same pixels in BGR order.
+        let bgr = [0u8, 0, 255, 255, 255, 255];
+        let source = ImageSource::from_bytes(&bgr, (2, 1))
+            .unwrap()
+            .with_channel_order(ChannelOrder::Bgr)
+            .unwrap();
+        assert_close(prepare(source), &expected);
+
+        // The same pixels with 16 bits per channel.
+        let rgb16: Vec<u16> = rgb.iter().map(|&c| c as u16 * 257).collect();
+        let tensor = NdTensor::from_data([1, 2, 3], rgb16);
+        let source = ImageSource::from_tensor(tensor.view(), DimOrder::Hwc).unwrap();
+        assert_close(prepare(source), &expected);
+
+        // Black pixels which are opaque, half transparent and fully
+        // transparent. Transparent pixels are composited onto white.
+        let grey_alpha = [0u8, 255, 0, 128, 0, 0];
+        let source = ImageSource::from_bytes(&grey_alpha, (3, 1)).unwrap();
+        assert_eq!(source.channel_order(), ChannelOrder::GreyAlpha);
+        assert_close(prepare(source), &[-0.5, -0.002, 0.5]);
+
+        let bgra = [0u8, 0, 255, 0, 0, 0, 255, 255];
+        let source = ImageSource::from_bytes(&bgra, (2, 1))
+            .unwrap()
+            .with_channel_order(ChannelOrder::Bgra)
+            .unwrap();
+        assert_close(prepare(source), &[0.5, BLACK_VALUE + 0.299]);
+
+        // 3x2 YUV frame, with luma values covering the video range.
+        let yuv = [16u8, 126, 235, 0, 255, 16, 128, 128, 128, 128];
+        let source = ImageSource::from_yuv420(&yuv, (3, 2)).unwrap();
+        assert_eq!(source.shape(), [3, 2, 3]);
+        assert_close(prepare(source), &[-0.5, 0.00228, 0.5, -0.5, 0.5, -0.5]);
+
+        let source = ImageSource::from_yuv420(&yuv, (3, 2))
+            .unwrap()
+            .with_channel_order(ChannelOrder::YuvFullRange)
+            .unwrap();
+        assert_eq!(source.channel_order(), ChannelOrder::YuvFullRange);
+        assert_close(
+            prepare(source),
+            &[-0.43725, -0.00588, 0.42157, -0.5, 0.5, -0.43725],
+        );
+
+        assert_eq!(
+            ImageSource::from_yuv420(&yuv[..9], (3, 2)).err(),
+            Some(ImageSourceError::InvalidDataLength)
+        );
+        assert_eq!(
+            ImageSource::from_yuv420(&[], (0, 2)).err(),
+            Some(ImageSourceError::EmptyImage)
+        );
+        assert_eq!(
+            ImageSource::from_bytes(&rgb, (2, 1))
+                .unwrap()
+                .with_channel_order(ChannelOrder::Bgra)
+                .err(),
+            Some(ImageSourceError::ChannelOrderMismatch)
+        );
+        assert_eq!(
+            ImageSource::from_bytes(&rgb, (2, 1))
+                .unwrap()
+                .with_channel_order(ChannelOrder::Yuv)
+                .err(),
+            Some(ImageSourceError::ChannelOrderMismatch)
+        );
+    }
+
+    #[test]
+    fn test_image_source_strided_region() {
+        // 3x2 RGB image whose rows are padded to 12 bytes.
+        let packed: Vec<u8> = (0..18).map(|i| i * 10).collect();
+        let mut padded = Vec::new();
+        for row in packed.chunks(9) {
+            padded.extend(row);
+            padded.extend([0xff; 3]);
+        }
+
+        let expected = prepare_image(ImageSource::from_bytes(&packed, (3, 2)).unwrap());
+        let source = ImageSource::from_strided_bytes(&padded, (3, 2), 3, 12).unwrap();
+        assert_eq!(source.shape(), [3, 2, 3]);
+        assert_eq!(prepare_image(source), expected);
+
+        // The padding after the last row may be omitted.
+        assert!(ImageSource::from_strided_bytes(&padded[..21], (3, 2), 3, 12).is_ok());
+        assert_eq!(
+            ImageSource::from_strided_bytes(&padded[..20], (3, 2), 3, 12).err(),
+            Some(ImageSourceError::InvalidDataLength)
+        );
+        assert_eq!(
+            ImageSource::from_strided_bytes(&padded, (3, 2), 3, 8).err(),
+            Some(ImageSourceError::InvalidStride)
+        );
+        for dimensions in [(0, 2), (3, 0)] {
+            assert_eq!(
+                ImageSource::from_strided_bytes(&padded, dimensions, 3, 12).err(),
+                Some(ImageSourceError::EmptyImage)
+            );
+        }
+
+        // Regions of images in HWC and CHW order.
+        let source = ImageSource::from_strided_bytes(&padded, (3, 2), 3, 12).unwrap();
+        let region = source.region(Rect::from_tlhw(1, 1, 1, 2)).unwrap();
+        assert_eq!(region.shape(), [3, 1, 2]);
+        assert_eq!(get_pixel(&region, 2, 0, 1), get_pixel(&source, 2, 1, 2));
+
+        let tensor = NdTensor::from_data([3, 2, 3], packed.clone());
+        let source = ImageSource::from_tensor(tensor.view(), DimOrder::Chw).unwrap();
+        let region = source.region(Rect::from_tlhw(0, 1, 2, 2)).unwrap();
+        assert_eq!(region.shape(), [3, 2, 2]);
+        assert_eq!(get_pixel(&region, 1, 1, 0), tensor[[1, 1, 1]] as f32 / 255.);
+
+        for rect in [Rect::from_tlhw(0, 2, 2, 2), Rect::from_tlhw(-1, 0, 1, 1)] {
+            assert_eq!(
+                source.region(rect).err(),
+                Some(ImageSourceError::InvalidRegion)
+            );
+        }
+
+        // YUV regions must be aligned with the chroma planes.
+        let yuv: Vec<u8> = (0..24).collect();
+        let source = ImageSource::from_yuv420(&yuv, (4, 4)).unwrap();
+        let region = source.region(Rect::from_tlhw(2, 2, 2, 2)).unwrap();
+        assert_eq!(get_pixel(&region, 0, 0, 0), 10. / 255.);
+        assert_eq!(get_pixel(&region, 1, 1, 1), 19. / 255.);
+        assert_eq!(get_pixel(&region, 2, 0, 0), 23. / 255.);
+        assert_eq!(
+            source.region(Rect::from_tlhw(1, 0, 2, 2)).err(),
+            Some(ImageSourceError::InvalidRegion)
+        );
+    }
+
+    #[test]
+    fn test_prepare_image_non_contiguous() {
+        // RGBA image in CHW order, and non-contiguous views of it in HWC order.
+        let chw =
+            NdTensor::<u8, 3>::from_fn([4, 3, 5], |[c, y, x]| (c * 60 + y * 20 + x * 7) as u8);
+        let expected = prepare_image(ImageSource::from_tensor(chw.view(), DimOrder::Chw).unwrap());
+        for y in 0..3 {
+            for x in 0..5 {
+                let luminance = chw[[0, y, x]] as f32 * 0.299
+                    + chw[[1, y, x]] as f32 * 0.587
+                    + chw[[2, y, x]] as f32 * 0.114;
+                let alpha = chw[[3, y, x]] as f32 / 255.;
+                let grey = (luminance / 255.) * alpha + (1. - alpha);
+                assert!((expected[[0, y, x]] - (BLACK_VALUE + grey)).abs() < 1e-5);
+            }
+        }
+
+        let assert_close = |actual: NdTensorView<f32, 3>, expected: NdTensorView<f32, 3>| {
+            assert_eq!(actual.shape(), expected.shape());
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                assert!((a - e).abs() < 1e-5);
+            }
+        };
+
+        let hwc = chw.permuted([1, 2, 0]);
+        let actual = prepare_image(ImageSource::from_tensor(hwc, DimOrder::Hwc).unwrap());
+        assert_close(actual.view(), expected.view());
+
+        // Every other column.
+        let hwc = hwc.slice::<3, _>((.., SliceRange::new(0, None, 2)));
+        let actual = prepare_image(ImageSource::from_tensor(hwc, DimOrder::Hwc).unwrap());
+        assert_close(
+            actual.view(),
+            expected.slice((.., .., SliceRange::new(0, None, 2))),
+        );
+    }
 }
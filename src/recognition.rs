@@ -1,20 +1,22 @@
 use core::f32;
 use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
 
-use anyhow::anyhow;
 use rayon::prelude::*;
 use rten::ctc::{CtcDecoder, CtcHypothesis};
 use rten::{thread_pool, Dimension, FloatOperators, Model, NodeId};
-use rten_imageproc::{
-    bounding_rect, BoundingRect, Line, Point, PointF, Polygon, Rect, RotatedRect,
-};
+use rten_imageproc::{BoundingRect, Line, Point, PointF, Polygon, Rect, RotatedRect};
 use rten_tensor::prelude::*;
 use rten_tensor::{NdTensor, NdTensorView, NdTensorViewMut, Tensor};
 
-use crate::errors::ModelRunError;
+use crate::errors::{ModelKind, ModelRunError, OcrError};
 use crate::geom_util::{downwards_line, leftmost_edge, rightmost_edge};
+use crate::model_metadata::ModelProperties;
 use crate::preprocess::BLACK_VALUE;
 use crate::text_items::{TextChar, TextLine};
+use crate::trace::{Span, TraceSink, TraceStage};
+use crate::DEFAULT_ALPHABET;
 
 /// Return a polygon which contains all the rects in `words`.
 ///
@@ -26,7 +28,7 @@ use crate::text_items::{TextChar, TextLine};
 /// to use [min_area_rect] on the union of the line's points. However the result
 /// will not tightly fit curved lines. This function returns a polygon which
 /// closely follows the edges of individual words.
-fn line_polygon(words: &[RotatedRect]) -> Vec<Point> {
+pub(crate) fn line_polygon(words: &[RotatedRect]) -> Vec<Point> {
     let mut polygon = Vec::new();
 
     let floor_point = |p: PointF| Point::from_yx(p.y as i32, p.x as i32);
@@ -54,54 +56,254 @@ fn line_polygon(words: &[RotatedRect]) -> Vec<Point> {
     polygon
 }
 
-/// Compute width to resize a text line image to, for a given height.
-fn resized_line_width(orig_width: i32, orig_height: i32, height: i32) -> u32 {
-    let min_width = 10.;
+/// Parameters that control batching and resizing of text lines for the
+/// recognition model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecognitionParams {
+    /// Maximum number of lines in each batch passed to the recognition model.
+    ///
+    /// Smaller batches reduce peak memory usage and allow more batches to be
+    /// processed in parallel, at the cost of lower throughput per batch.
+    pub max_lines_per_batch: usize,
+
+    /// Lines are grouped into batches by their width after resizing, rounded
+    /// up to a multiple of this value. Lines in a batch are padded to a common
+    /// width.
+    ///
+    /// Larger values produce fewer, larger batches with more wasted padding.
+    pub batch_width_step: u32,
+
+    /// Maximum width of a line image passed to the recognition model, after
+    /// resizing the line to the model's input height.
+    ///
+    /// Lines which would be wider than this are split into overlapping chunks
+    /// which are recognized separately, and the results are merged.
+    pub max_line_width: u32,
+
+    /// Overlap between adjacent chunks of lines that are split because they
+    /// exceed [max_line_width](RecognitionParams::max_line_width), measured
+    /// in the same units. Must be less than `max_line_width`.
+    pub chunk_overlap: u32,
+}
 
-    // A larger maximum width avoids horizontally squashing long input lines,
-    // affecting accuracy. However it also increases the processing time.
-    //
-    // The current value was chosen to be large enough to produce good results
-    // on screenshots taken from the longest lines in English Wikipedia articles
-    // (image size approx 1860x30, 150 characters).
-    //
-    // The widest image seen during training may be constrained to a shorter
-    // value than this, but we rely on the model's ability to generalize to
-    // longer sequences.
-    let max_width = 2400.;
+impl Default for RecognitionParams {
+    fn default() -> RecognitionParams {
+        RecognitionParams {
+            max_lines_per_batch: 20,
+            batch_width_step: 50,
+
+            // A larger maximum width avoids splitting long input lines, which
+            // can affect accuracy at chunk boundaries. However it also
+            // increases the processing time and memory usage.
+            //
+            // The current value was chosen to be large enough to produce good
+            // results on screenshots taken from the longest lines in English
+            // Wikipedia articles (image size approx 1860x30, 150 characters).
+            //
+            // The widest image seen during training may be constrained to a
+            // shorter value than this, but we rely on the model's ability to
+            // generalize to longer sequences.
+            max_line_width: 2400,
+
+            // Several characters at the default input height.
+            chunk_overlap: 200,
+        }
+    }
+}
 
+/// Compute width to resize a text line image to, for a given height.
+///
+/// The result is clamped to at most `max_width`.
+fn resized_line_width(orig_width: i32, orig_height: i32, height: i32, max_width: u32) -> u32 {
+    let min_width = 10.;
     let aspect_ratio = orig_width as f32 / orig_height as f32;
-    (height as f32 * aspect_ratio).clamp(min_width, max_width) as u32
+    (height as f32 * aspect_ratio).clamp(min_width, (max_width as f32).max(min_width)) as u32
 }
 
 /// Details about a text line needed to prepare the input to the text
 /// recognition model.
 #[derive(Clone)]
 struct TextRecLine {
+    /// Index of the image which this line belongs to, in the list of images
+    /// being recognized together.
+    image_index: usize,
+
     /// Index of this line in the list of lines found in the image.
     index: usize,
 
+    /// Index of this entry in the list of chunks that the line was split into.
+    chunk_index: usize,
+
+    /// Number of chunks that the line was split into. This is 1 for lines that
+    /// fit within [RecognitionParams::max_line_width].
+    chunk_count: usize,
+
     /// Region of the image containing this line.
     region: Polygon,
 
+    /// Part of the line's bounding rect which this entry covers.
+    crop: Rect,
+
+    /// Range of X coordinates for which recognized characters are kept. Chunks
+    /// of split lines overlap, and characters which start in the overlap are
+    /// taken from the chunk closest to them. See [merge_chunk_chars].
+    keep_x: Range<i32>,
+
     /// Width to resize this line to.
     resized_width: u32,
 }
 
+/// Return the polygon and bounding rect of the part of a text line which is
+/// inside `page_rect`.
+///
+/// Returns an error if the line has no words or the word coordinates are not
+/// finite. Returns `Ok(None)` if the line is entirely outside the page.
+fn line_region(
+    word_rects: &[RotatedRect],
+    page_rect: Rect,
+) -> Result<Option<(Polygon, Rect)>, OcrError> {
+    if word_rects.is_empty() {
+        return Err(OcrError::InvalidInput("line has no words".into()));
+    }
+    let finite_coords = word_rects
+        .iter()
+        .flat_map(|r| r.corners())
+        .all(|p| p.x.is_finite() && p.y.is_finite());
+    if !finite_coords {
+        return Err(OcrError::InvalidInput(
+            "word rect has non-finite coordinates".into(),
+        ));
+    }
+
+    // Clamp vertices to the page. This avoids creating huge line images for
+    // words that extend far outside the page, and changes nothing inside it.
+    let polygon: Vec<Point> = line_polygon(word_rects)
+        .into_iter()
+        .map(|p| {
+            Point::from_yx(
+                p.y.clamp(page_rect.top(), page_rect.bottom()),
+                p.x.clamp(page_rect.left(), page_rect.right()),
+            )
+        })
+        .collect();
+    let region = Polygon::new(polygon);
+    let line_rect = region.bounding_rect();
+    if line_rect.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some((region, line_rect)))
+}
+
+/// Maximum number of chunks that a line is split into by [split_text_line].
+///
+/// Lines which would need more chunks, such as very short and wide lines, are
+/// squashed horizontally to fit.
+const MAX_LINE_CHUNKS: u32 = 8;
+
+/// Prepare the entries for a text line which are passed to the recognition
+/// model.
+///
+/// Lines which are wider than [RecognitionParams::max_line_width] after
+/// resizing to the model's input height are split into overlapping chunks,
+/// rather than being squashed horizontally.
+fn split_text_line(
+    image_index: usize,
+    index: usize,
+    region: Polygon,
+    line_rect: Rect,
+    rec_img_height: u32,
+    params: &RecognitionParams,
+) -> Vec<TextRecLine> {
+    let max_full_width = params.max_line_width.saturating_add(
+        (MAX_LINE_CHUNKS - 1).saturating_mul(params.max_line_width - params.chunk_overlap),
+    );
+    let full_width = resized_line_width(
+        line_rect.width(),
+        line_rect.height(),
+        rec_img_height as i32,
+        max_full_width,
+    );
+
+    if full_width <= params.max_line_width {
+        return vec![TextRecLine {
+            image_index,
+            index,
+            chunk_index: 0,
+            chunk_count: 1,
+            region,
+            crop: line_rect,
+            keep_x: i32::MIN..i32::MAX,
+            resized_width: full_width,
+        }];
+    }
+
+    let max_width = params.max_line_width as f32;
+    let overlap = params.chunk_overlap as f32;
+    let step = max_width - overlap;
+    let full_width = full_width as f32;
+    let chunk_count = ((full_width - overlap) / step).ceil() as usize;
+
+    // Number of input image pixels per resized pixel.
+    let scale = line_rect.width() as f32 / full_width;
+    let to_image_x = |x: f32| line_rect.left() + (x * scale).round() as i32;
+
+    (0..chunk_count)
+        .map(|chunk_index| {
+            let start = chunk_index as f32 * step;
+            let end = (start + max_width).min(full_width);
+            let crop = Rect::from_tlbr(
+                line_rect.top(),
+                to_image_x(start),
+                line_rect.bottom(),
+                to_image_x(end),
+            );
+
+            // Split the overlap between adjacent chunks at its midpoint.
+            let keep_start = if chunk_index == 0 {
+                i32::MIN
+            } else {
+                to_image_x(start + overlap / 2.)
+            };
+            let keep_end = if chunk_index == chunk_count - 1 {
+                i32::MAX
+            } else {
+                to_image_x(start + step + overlap / 2.)
+            };
+
+            TextRecLine {
+                image_index,
+                index,
+                chunk_index,
+                chunk_count,
+                region: region.clone(),
+                crop,
+                keep_x: keep_start..keep_end,
+                resized_width: ((end - start).round() as u32).clamp(1, params.max_line_width),
+            }
+        })
+        .collect()
+}
+
+/// Extract the part of `line_region` which is inside `crop` from `image`, and
+/// resize it to `[output_height, resized_width]`.
 fn prepare_text_line(
     image: NdTensorView<f32, 3>,
     page_rect: Rect,
     line_region: &Polygon,
+    crop: Rect,
     resized_width: u32,
     output_height: usize,
-) -> NdTensor<f32, 2> {
+) -> Result<NdTensor<f32, 2>, OcrError> {
     // Page rect adjusted to only contain coordinates that are valid for
     // indexing into the input image.
     let page_index_rect = page_rect.adjust_tlbr(0, 0, -1, -1);
 
     let grey_chan = image.slice([0]);
 
-    let line_rect = line_region.bounding_rect();
+    let line_rect = crop;
+    let line_index_rect =
+        Rect::from_hw(line_rect.height(), line_rect.width()).adjust_tlbr(0, 0, -1, -1);
     let mut line_img = NdTensor::full(
         [line_rect.height() as usize, line_rect.width() as usize],
         BLACK_VALUE,
@@ -109,7 +311,7 @@ fn prepare_text_line(
 
     for in_p in line_region.fill_iter() {
         let out_p = Point::from_yx(in_p.y - line_rect.top(), in_p.x - line_rect.left());
-        if !page_index_rect.contains_point(in_p) || !page_index_rect.contains_point(out_p) {
+        if !page_index_rect.contains_point(in_p) || !line_index_rect.contains_point(out_p) {
             continue;
         }
         line_img[[out_p.y as usize, out_p.x as usize]] =
@@ -119,42 +321,46 @@ fn prepare_text_line(
     let resized_line_img = line_img
         .reshaped([1, 1, line_img.size(0), line_img.size(1)])
         .resize_image([output_height, resized_width as usize])
-        .unwrap();
+        .map_err(|err| OcrError::ImageProcessingFailed(err.into()))?;
 
     let out_shape = [resized_line_img.size(2), resized_line_img.size(3)];
-    resized_line_img.into_shape(out_shape)
+    Ok(resized_line_img.into_shape(out_shape))
 }
 
 /// Prepare an NCHW tensor containing a batch of text line images, for input
 /// into the text recognition model.
 ///
-/// For each line in `lines`, the line region is extracted from `image`, resized
-/// to a fixed `output_height` and a line-specific width, then copied to the
-/// output tensor. Lines in the batch can have different widths, so the output
-/// is padded on the right side to a common width of `output_width`.
+/// For each line in `lines`, the line region is extracted from the image in
+/// `images` that the line belongs to, resized to a fixed `output_height` and a
+/// line-specific width, then copied to the output tensor. Lines in the batch
+/// can have different widths, so the output is padded on the right side to a
+/// common width of `output_width`.
 fn prepare_text_line_batch(
-    image: &NdTensorView<f32, 3>,
+    images: &[NdTensorView<f32, 3>],
     lines: &[TextRecLine],
-    page_rect: Rect,
     output_height: usize,
     output_width: usize,
-) -> NdTensor<f32, 4> {
+) -> Result<NdTensor<f32, 4>, OcrError> {
     let mut output = NdTensor::full([lines.len(), 1, output_height, output_width], BLACK_VALUE);
 
     for (group_line_index, line) in lines.iter().enumerate() {
+        let image = images[line.image_index].view();
+        let [_, img_height, img_width] = image.shape();
+        let page_rect = Rect::from_hw(img_height as i32, img_width as i32);
         let resized_line_img = prepare_text_line(
-            image.view(),
+            image,
             page_rect,
             &line.region,
+            line.crop,
             line.resized_width,
             output_height,
-        );
+        )?;
         output
             .slice_mut((group_line_index, 0, .., ..(line.resized_width as usize)))
             .copy_from(&resized_line_img);
     }
 
-    output
+    Ok(output)
 }
 
 /// Return the bounding rectangle of the slice of a polygon with X coordinates
@@ -206,14 +412,13 @@ pub enum DecodeMethod {
 
 #[derive(Clone, Default)]
 pub struct RecognitionOpt<'a> {
-    pub debug: bool,
-
     /// Method used to decode character sequence outputs to character values.
     pub decode_method: DecodeMethod,
 
-    pub alphabet: &'a str,
-
     pub excluded_char_labels: Option<&'a [usize]>,
+
+    /// Receiver for timing events from recognition.
+    pub trace_sink: Option<&'a dyn TraceSink>,
 }
 
 /// Input and output from recognition for a single text line.
@@ -233,19 +438,66 @@ struct LineRecResult {
     ctc_output: CtcHypothesis,
 }
 
+impl LineRecResult {
+    /// Return the width, in input image pixels, of the part of the line
+    /// covered by one step of the recognition model's output sequence.
+    fn step_width(&self) -> f32 {
+        let downsample_factor = (self.rec_input_len as f32 / self.ctc_input_len as f32).round();
+        downsample_factor * self.line.crop.width() as f32 / self.line.resized_width as f32
+    }
+}
+
+/// Characters recognized in one chunk of a line which was split by
+/// [split_text_line].
+#[derive(Clone)]
+struct ChunkChars {
+    keep_x: Range<i32>,
+
+    /// See [LineRecResult::step_width].
+    step_width: f32,
+
+    chars: Vec<TextChar>,
+}
+
+/// Concatenate the characters recognized in the chunks of a split line.
+///
+/// Positions of characters are only known to the nearest step of the
+/// recognition model's output, and adjacent chunks are not aligned to the
+/// same steps. A character near the boundary between the kept ranges of two
+/// chunks can therefore appear to be on different sides of it in each chunk.
+/// To avoid dropping such characters, a chunk's characters are kept if they
+/// start up to one step before its range. To avoid duplicating them,
+/// characters which start less than one step after the last character taken
+/// from the previous chunk are skipped.
+fn merge_chunk_chars(chunks: impl IntoIterator<Item = ChunkChars>) -> Vec<TextChar> {
+    let mut merged: Vec<TextChar> = Vec::new();
+    for chunk in chunks {
+        let tolerance = (chunk.step_width.round() as i32).max(1);
+        let min_x = chunk.keep_x.start.saturating_sub(tolerance);
+        let min_x = match merged.last() {
+            Some(last) => min_x.max(last.rect.left().saturating_add(tolerance)),
+            None => min_x,
+        };
+        merged.extend(chunk.chars.into_iter().filter(|c| {
+            let x = c.rect.left();
+            x >= min_x && x < chunk.keep_x.end
+        }));
+    }
+    merged
+}
+
 /// Combine information from the input and output of text line recognition
-/// to produce [TextLine]s containing character sequences and bounding boxes
-/// for each line.
+/// to produce character sequences and bounding boxes for each line.
 ///
-/// Entries in the result may be `None` if no text was recognized for a line.
-fn text_lines_from_recognition_results(
+/// Entries in the result may be empty if no text was recognized for a line.
+fn line_chars_from_recognition_results(
     results: &[LineRecResult],
     alphabet: &str,
-) -> Vec<Option<TextLine>> {
+) -> Vec<Vec<TextChar>> {
     results
         .iter()
         .map(|result| {
-            let line_rect = result.line.region.bounding_rect();
+            let line_rect = result.line.crop;
             let x_scale_factor = (line_rect.width() as f32) / (result.line.resized_width as f32);
             // Calculate how much the recognition model downscales the image
             // width. We assume this will be an integer factor, or close to it
@@ -260,9 +512,9 @@ fn text_lines_from_recognition_results(
                 .enumerate()
                 .filter_map(|(i, step)| {
                     // X coord range of character in line recognition input image.
-                    let start_x = step.pos * downsample_factor;
+                    let start_x = step.pos.saturating_mul(downsample_factor);
                     let end_x = if let Some(next_step) = steps.get(i + 1) {
-                        next_step.pos * downsample_factor
+                        next_step.pos.saturating_mul(downsample_factor)
                     } else {
                         result.line.resized_width
                     };
@@ -296,16 +548,17 @@ fn text_lines_from_recognition_results(
                             start_x,
                             end_x,
                         )
-                        .expect("invalid X coords"),
+                        .unwrap_or(Rect::from_tlbr(
+                            line_rect.top(),
+                            start_x,
+                            line_rect.bottom(),
+                            end_x,
+                        )),
                     })
                 })
                 .collect();
 
-            if text_line.is_empty() {
-                None
-            } else {
-                Some(TextLine::new(text_line))
-            }
+            text_line
         })
         .collect()
 }
@@ -315,42 +568,123 @@ fn text_lines_from_recognition_results(
 pub struct TextRecognizer {
     model: Model,
     input_id: NodeId,
-    input_shape: Vec<Dimension>,
+    input_height: u32,
     output_id: NodeId,
+    alphabet: String,
+    params: RecognitionParams,
 }
 
 impl TextRecognizer {
     /// Initialize a text recognizer from a trained RTen model. Fails if the
     /// model does not have the expected inputs or outputs.
-    pub fn from_model(model: Model) -> anyhow::Result<TextRecognizer> {
+    ///
+    /// `alphabet` specifies the characters corresponding to the model's output
+    /// classes. If `None`, the alphabet embedded in the model's metadata is
+    /// used, or [DEFAULT_ALPHABET] if the model does not specify one. The
+    /// alphabet is validated against the model's output shape, if known.
+    pub fn from_model(
+        model: Model,
+        params: RecognitionParams,
+        alphabet: Option<String>,
+    ) -> Result<TextRecognizer, OcrError> {
+        let invalid_model = |msg: String| OcrError::InvalidModel(ModelKind::Recognition, msg);
+
+        if params.max_lines_per_batch == 0 || params.batch_width_step == 0 {
+            return Err(OcrError::InvalidConfig(
+                "recognition batch size and width step must be greater than zero".into(),
+            ));
+        }
+        if params.chunk_overlap >= params.max_line_width {
+            return Err(OcrError::InvalidConfig(format!(
+                "recognition chunk overlap ({}) must be less than max line width ({})",
+                params.chunk_overlap, params.max_line_width
+            )));
+        }
+
+        let props = ModelProperties::from_model(&model, ModelKind::Recognition)?;
+
         let input_id = model
             .input_ids()
             .first()
             .copied()
-            .ok_or(anyhow!("recognition model has no inputs"))?;
+            .ok_or_else(|| invalid_model("model has no inputs".into()))?;
         let input_shape = model
             .node_info(input_id)
             .and_then(|info| info.shape())
-            .ok_or(anyhow!("recognition model does not specify input shape"))?;
+            .ok_or_else(|| invalid_model("model does not specify input shape".into()))?;
         let output_id = model
             .output_ids()
             .first()
             .copied()
-            .ok_or(anyhow!("recognition model has no outputs"))?;
+            .ok_or_else(|| invalid_model("model has no outputs".into()))?;
+
+        let [_, channels, height, _] = &input_shape[..] else {
+            return Err(invalid_model(format!(
+                "input has {} dims but expected 4",
+                input_shape.len()
+            )));
+        };
+        if let Dimension::Fixed(channels) = channels {
+            if *channels != 1 {
+                return Err(invalid_model(format!(
+                    "input has {} channels but expected 1",
+                    channels
+                )));
+            }
+        }
+        let input_height = match (height, props.input_height) {
+            (Dimension::Fixed(size), Some(meta_height)) if *size != meta_height as usize => {
+                return Err(invalid_model(format!(
+                    "input height ({}) does not match metadata ({})",
+                    size, meta_height
+                )));
+            }
+            (Dimension::Fixed(size), _) => (*size)
+                .try_into()
+                .map_err(|_| invalid_model("input height is too large".into()))?,
+            (Dimension::Symbolic(_), meta_height) => meta_height.unwrap_or(50),
+        };
+
+        let alphabet = alphabet
+            .or(props.alphabet)
+            .unwrap_or_else(|| DEFAULT_ALPHABET.to_string());
+        if alphabet.is_empty() {
+            return Err(OcrError::InvalidConfig(
+                "recognition alphabet is empty".into(),
+            ));
+        }
+
+        // Check the alphabet up front if the output class count is known,
+        // rather than failing when recognition is first run.
+        let output_shape = model.node_info(output_id).and_then(|info| info.shape());
+        if let Some(Dimension::Fixed(n_classes)) = output_shape.as_ref().and_then(|s| s.last()) {
+            let alphabet_len = alphabet.chars().count();
+            if alphabet_len + 1 != *n_classes {
+                return Err(OcrError::AlphabetMismatch {
+                    alphabet_len,
+                    output_classes: *n_classes,
+                });
+            }
+        }
+
         Ok(TextRecognizer {
             model,
             input_id,
-            input_shape: input_shape.into_iter().collect(),
+            input_height,
             output_id,
+            alphabet,
+            params,
         })
     }
 
+    /// Return the alphabet used to map output classes to characters.
+    pub fn alphabet(&self) -> &str {
+        &self.alphabet
+    }
+
     /// Return the expected height of input line images.
     fn input_height(&self) -> u32 {
-        match self.input_shape[2] {
-            Dimension::Fixed(size) => size.try_into().unwrap(),
-            Dimension::Symbolic(_) => 50,
-        }
+        self.input_height
     }
 
     /// Run text recognition on an NCHW batch of text line images, and return
@@ -383,30 +717,35 @@ impl TextRecognizer {
     /// Prepare a text line for input into the recognition model.
     ///
     /// This method exists for model debugging purposes to expose the
-    /// preprocessing that [TextRecognizer::recognize_text_lines] does.
+    /// preprocessing that [TextRecognizer::recognize_text_lines] does. Lines
+    /// which [TextRecognizer::recognize_text_lines] would split into chunks are
+    /// instead resized to [RecognitionParams::max_line_width].
     pub fn prepare_input(
         &self,
         image: NdTensorView<f32, 3>,
         line: &[RotatedRect],
-    ) -> NdTensor<f32, 2> {
+    ) -> Result<NdTensor<f32, 2>, OcrError> {
         // These lines should match corresponding code in
         // `recognize_text_lines`.
         let [_, img_height, img_width] = image.shape();
         let page_rect = Rect::from_hw(img_height as i32, img_width as i32);
 
-        let line_rect = bounding_rect(line.iter())
-            .expect("line has no words")
-            .integral_bounding_rect();
-
-        let line_poly = Polygon::new(line_polygon(line));
+        let Some((line_poly, line_rect)) = line_region(line, page_rect)? else {
+            return Err(OcrError::InvalidInput("line is outside the image".into()));
+        };
         let rec_img_height = self.input_height();
-        let resized_width =
-            resized_line_width(line_rect.width(), line_rect.height(), rec_img_height as i32);
+        let resized_width = resized_line_width(
+            line_rect.width(),
+            line_rect.height(),
+            rec_img_height as i32,
+            self.params.max_line_width,
+        );
 
         prepare_text_line(
             image,
             page_rect,
             &line_poly,
+            line_rect,
             resized_width,
             rec_img_height as usize,
         )
@@ -427,16 +766,66 @@ impl TextRecognizer {
         image: NdTensorView<f32, 3>,
         lines: &[Vec<RotatedRect>],
         opts: RecognitionOpt,
-    ) -> anyhow::Result<Vec<Option<TextLine>>> {
+    ) -> Result<Vec<Option<TextLine>>, OcrError> {
+        let mut text_lines = self.recognize_text_lines_batch(&[(image, lines)], opts)?;
+        Ok(text_lines.remove(0))
+    }
+
+    /// Recognize text lines in several images.
+    ///
+    /// This is like [TextRecognizer::recognize_text_lines], but lines from all
+    /// images are pooled together when forming recognition batches. This
+    /// produces fuller batches when there are many images with few lines each.
+    ///
+    /// Returns one list of text lines for each entry in `images`.
+    pub fn recognize_text_lines_batch(
+        &self,
+        images: &[(NdTensorView<f32, 3>, &[Vec<RotatedRect>])],
+        opts: RecognitionOpt,
+    ) -> Result<Vec<Vec<Option<TextLine>>>, OcrError> {
+        let text_lines: Vec<Mutex<Vec<Option<TextLine>>>> = images
+            .iter()
+            .map(|(_, lines)| Mutex::new(vec![None; lines.len()]))
+            .collect();
+
+        self.recognize_text_lines_streaming(images, opts, |image_index, line_index, text_line| {
+            text_lines[image_index]
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)[line_index] = text_line;
+        })?;
+
+        Ok(text_lines
+            .into_iter()
+            .map(|lines| lines.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect())
+    }
+
+    /// Recognize text lines in several images, reporting results for each
+    /// line as soon as the batch containing it has been processed.
+    ///
+    /// `on_line` is called with `(image_index, line_index, text_line)` for each
+    /// line in `images`. Batches are processed in parallel, so calls can come
+    /// from different threads and in any order. If recognition fails, results
+    /// for some lines may already have been reported.
+    pub fn recognize_text_lines_streaming<F>(
+        &self,
+        images: &[(NdTensorView<f32, 3>, &[Vec<RotatedRect>])],
+        opts: RecognitionOpt,
+        on_line: F,
+    ) -> Result<(), OcrError>
+    where
+        F: Fn(usize, usize, Option<TextLine>) + Sync,
+    {
         let RecognitionOpt {
-            debug,
             decode_method,
-            alphabet,
             excluded_char_labels,
+            trace_sink,
         } = opts;
+        let alphabet = self.alphabet.as_str();
 
-        let [_, img_height, img_width] = image.shape();
-        let page_rect = Rect::from_hw(img_height as i32, img_width as i32);
+        let mut span = Span::enter(trace_sink, TraceStage::Recognition);
+        span.record("images", images.len());
+        span.record("lines", images.iter().map(|(_, lines)| lines.len()).sum());
 
         // Group lines into batches which will have similar widths after resizing
         // to a fixed height.
@@ -447,28 +836,44 @@ impl TextRecognizer {
         // such that all line images have a similar width reduces this wastage.
         // There is a trade-off between maximizing the batch size and minimizing
         // the variance in width of images in the batch.
+        //
+        // Lines from all images are pooled together, so that many images with
+        // few lines each still produce full batches.
         let rec_img_height = self.input_height();
         let mut line_groups: HashMap<i32, Vec<TextRecLine>> = HashMap::new();
-        for (line_index, word_rects) in lines.iter().enumerate() {
-            let line_rect = bounding_rect(word_rects.iter())
-                .expect("line has no words")
-                .integral_bounding_rect();
-            let resized_width =
-                resized_line_width(line_rect.width(), line_rect.height(), rec_img_height as i32);
-            let group_width = resized_width.next_multiple_of(50);
-            line_groups
-                .entry(group_width as i32)
-                .or_default()
-                .push(TextRecLine {
-                    index: line_index,
-                    region: Polygon::new(line_polygon(word_rects)),
-                    resized_width,
-                });
+        for (image_index, (image, lines)) in images.iter().enumerate() {
+            let [_, img_height, img_width] = image.shape();
+            let page_rect = Rect::from_hw(img_height as i32, img_width as i32);
+
+            for (line_index, word_rects) in lines.iter().enumerate() {
+                let Some((region, line_rect)) = line_region(word_rects, page_rect)? else {
+                    // Lines entirely outside the image have no text.
+                    on_line(image_index, line_index, None);
+                    continue;
+                };
+                for rec_line in split_text_line(
+                    image_index,
+                    line_index,
+                    region,
+                    line_rect,
+                    rec_img_height,
+                    &self.params,
+                ) {
+                    let group_width = rec_line
+                        .resized_width
+                        .next_multiple_of(self.params.batch_width_step);
+                    line_groups
+                        .entry(group_width as i32)
+                        .or_default()
+                        .push(rec_line);
+                }
+            }
         }
+        let images: Vec<NdTensorView<f32, 3>> = images.iter().map(|(image, _)| *image).collect();
 
         // Split large line groups up into smaller batches that can be processed
         // in parallel.
-        let max_lines_per_group = 20;
+        let max_lines_per_group = self.params.max_lines_per_batch;
         let line_groups: Vec<(i32, Vec<TextRecLine>)> = line_groups
             .into_iter()
             .flat_map(|(group_width, lines)| {
@@ -481,83 +886,118 @@ impl TextRecognizer {
 
         let alphabet_len = alphabet.chars().count();
 
+        // Recognized characters from chunks of split lines, for lines where
+        // some chunks have not been processed yet.
+        let pending_chunks = Mutex::new(HashMap::new());
+
         // Run text recognition on batches of lines.
-        let batch_rec_results: Result<Vec<Vec<LineRecResult>>, ModelRunError> =
-            thread_pool().run(|| {
-                line_groups
-                    .into_par_iter()
-                    .map(|(group_width, lines)| {
-                        if debug {
-                            println!(
-                                "Processing group of {} lines of width {}",
-                                lines.len(),
-                                group_width,
-                            );
-                        }
-
-                        let rec_input = prepare_text_line_batch(
-                            &image,
-                            &lines,
-                            page_rect,
-                            rec_img_height as usize,
-                            group_width as usize,
-                        );
-
-                        let mut rec_output = self.run(rec_input)?;
-
-                        if alphabet_len + 1 != rec_output.size(2) {
-                            return Err(ModelRunError::WrongOutput(format!(
-                                "output column count ({}) does not match alphabet size ({})",
-                                rec_output.size(2),
-                                alphabet_len + 1
-                            )));
-                        }
-
-                        let ctc_input_len = rec_output.shape()[1];
-
-                        // Apply CTC decoding to get the label sequence for each line.
-                        let line_rec_results = lines
-                            .into_iter()
-                            .enumerate()
-                            .map(|(group_line_index, line)| {
-                                let decoder = CtcDecoder::new();
-
-                                let mut input_seq_slice = rec_output.slice_mut([group_line_index]);
-                                let input_seq = Self::filter_excluded_char_labels(
-                                    excluded_char_labels,
-                                    &mut input_seq_slice,
-                                );
-
-                                let ctc_output = match decode_method {
-                                    DecodeMethod::Greedy => decoder.decode_greedy(input_seq),
-                                    DecodeMethod::BeamSearch { width } => {
-                                        decoder.decode_beam(input_seq, width)
-                                    }
-                                };
-                                LineRecResult {
-                                    line,
-                                    rec_input_len: group_width as usize,
-                                    ctc_input_len,
-                                    ctc_output,
-                                }
-                            })
-                            .collect::<Vec<_>>();
+        let batch_rec_results: Result<(), OcrError> = thread_pool().run(|| {
+            line_groups
+                .into_par_iter()
+                .try_for_each(|(group_width, lines)| {
+                    let mut batch_span = Span::enter(trace_sink, TraceStage::RecognitionBatch);
+                    batch_span.record("lines", lines.len());
+                    batch_span.record("width", group_width as usize);
+                    batch_span.record(
+                        "padding",
+                        lines
+                            .iter()
+                            .map(|line| (group_width as u32 - line.resized_width) as usize)
+                            .sum(),
+                    );
+
+                    let rec_input = prepare_text_line_batch(
+                        &images,
+                        &lines,
+                        rec_img_height as usize,
+                        group_width as usize,
+                    )?;
+
+                    let mut rec_output = self.run(rec_input)?;
+
+                    if alphabet_len + 1 != rec_output.size(2) {
+                        return Err(OcrError::AlphabetMismatch {
+                            alphabet_len,
+                            output_classes: rec_output.size(2),
+                        });
+                    }
 
-                        Ok(line_rec_results)
-                    })
-                    .collect()
-            });
+                    let ctc_input_len = rec_output.shape()[1];
 
-        let mut line_rec_results: Vec<LineRecResult> =
-            batch_rec_results?.into_iter().flatten().collect();
+                    // Apply CTC decoding to get the label sequence for each line.
+                    let line_rec_results = lines
+                        .into_iter()
+                        .enumerate()
+                        .map(|(group_line_index, line)| {
+                            let decoder = CtcDecoder::new();
 
-        // The recognition outputs are in a different order than the inputs due to
-        // batching and parallel processing. Re-sort them into input order.
-        line_rec_results.sort_by_key(|result| result.line.index);
+                            let mut input_seq_slice = rec_output.slice_mut([group_line_index]);
+                            let input_seq = Self::filter_excluded_char_labels(
+                                excluded_char_labels,
+                                &mut input_seq_slice,
+                            );
 
-        let text_lines = text_lines_from_recognition_results(&line_rec_results, alphabet);
+                            let ctc_output = match decode_method {
+                                DecodeMethod::Greedy => decoder.decode_greedy(input_seq),
+                                DecodeMethod::BeamSearch { width } => {
+                                    decoder.decode_beam(input_seq, width)
+                                }
+                            };
+                            LineRecResult {
+                                line,
+                                rec_input_len: group_width as usize,
+                                ctc_input_len,
+                                ctc_output,
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+                    // The recognition outputs are in a different order than
+                    // the inputs due to batching and parallel processing, so
+                    // each result is reported with its input position.
+                    for (result, chars) in
+                        line_rec_results
+                            .iter()
+                            .zip(line_chars_from_recognition_results(
+                                &line_rec_results,
+                                alphabet,
+                            ))
+                    {
+                        let line = &result.line;
+                        let chars = if line.chunk_count == 1 {
+                            chars
+                        } else {
+                            // Wait for all chunks of a split line, then
+                            // concatenate their characters.
+                            let mut pending = pending_chunks
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner);
+                            let chunks = pending
+                                .entry((line.image_index, line.index))
+                                .or_insert_with(|| vec![None; line.chunk_count]);
+                            chunks[line.chunk_index] = Some(ChunkChars {
+                                keep_x: line.keep_x.clone(),
+                                step_width: result.step_width(),
+                                chars,
+                            });
+                            if chunks.iter().any(|chunk| chunk.is_none()) {
+                                continue;
+                            }
+                            let chunks = pending
+                                .remove(&(line.image_index, line.index))
+                                .unwrap_or_default();
+                            merge_chunk_chars(chunks.into_iter().flatten())
+                        };
+                        let text_line = TextLine::try_new(chars);
+                        on_line(line.image_index, line.index, text_line);
+                    }
+
+                    Ok(())
+                })
+        });
+        batch_rec_results?;
 
-        Ok(text_lines)
+        Ok(())
     }
 
     /// Post-process recognition model outputs to filter excluded characters.
@@ -584,9 +1024,9 @@ impl TextRecognizer {
 
 #[cfg(test)]
 mod tests {
-    use rten_imageproc::{BoundingRect, Point, Polygon, RotatedRect, Vec2};
+    use rten_imageproc::{BoundingRect, Point, PointF, Polygon, Rect, RotatedRect, Vec2};
 
-    use super::line_polygon;
+    use super::{line_polygon, line_region, split_text_line, RecognitionParams, MAX_LINE_CHUNKS};
 
     #[test]
     fn test_line_polygon() {
@@ -614,4 +1054,86 @@ mod tests {
             )));
         }
     }
+
+    #[test]
+    fn test_split_text_line() {
+        let params = RecognitionParams::default();
+        let rec_img_height = 64;
+        let page_rect = Rect::from_hw(100, 2000);
+        let split = |line: &[RotatedRect]| {
+            let (region, line_rect) = line_region(line, page_rect).unwrap().unwrap();
+            split_text_line(0, 0, region, line_rect, rec_img_height, &params)
+        };
+
+        // Line which fits within the maximum width.
+        let short_line = [RotatedRect::from_rect(
+            Rect::from_tlhw(10, 10, 20, 200).to_f32(),
+        )];
+        let chunks = split(&short_line);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].crop, Rect::from_tlhw(10, 10, 20, 200));
+        assert_eq!(chunks[0].keep_x, i32::MIN..i32::MAX);
+
+        // Line which is 3200px wide when resized to the model's input height,
+        // and so must be split.
+        let line_rect = Rect::from_tlhw(10, 10, 20, 1000);
+        let long_line = [RotatedRect::from_rect(line_rect.to_f32())];
+        let chunks = split(&long_line);
+        assert_eq!(chunks.len(), 2);
+
+        assert_eq!(chunks[0].crop.left(), line_rect.left());
+        assert_eq!(chunks[chunks.len() - 1].crop.right(), line_rect.right());
+        assert_eq!(chunks[0].keep_x.start, i32::MIN);
+        assert_eq!(chunks[chunks.len() - 1].keep_x.end, i32::MAX);
+
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.chunk_index, chunk_index);
+            assert_eq!(chunk.chunk_count, chunks.len());
+            assert!(chunk.resized_width <= params.max_line_width);
+            assert_eq!(chunk.crop.top(), line_rect.top());
+            assert_eq!(chunk.crop.bottom(), line_rect.bottom());
+        }
+
+        for (prev, next) in chunks.iter().zip(chunks.iter().skip(1)) {
+            // Adjacent chunks overlap, and the overlap is split between them.
+            assert!(next.crop.left() < prev.crop.right());
+            assert_eq!(prev.keep_x.end, next.keep_x.start);
+            assert!(prev.keep_x.end > next.crop.left() && prev.keep_x.end < prev.crop.right());
+        }
+
+        // Line which is very short and wide, and is squashed so that it does
+        // not need more than the maximum number of chunks.
+        let line_rect = Rect::from_tlhw(10, 10, 1, 1900);
+        let wide_line = [RotatedRect::from_rect(line_rect.to_f32())];
+        let chunks = split(&wide_line);
+        assert_eq!(chunks.len(), MAX_LINE_CHUNKS as usize);
+        assert_eq!(chunks[chunks.len() - 1].crop.right(), line_rect.right());
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.resized_width <= params.max_line_width));
+    }
+
+    #[test]
+    fn test_line_region_degenerate_lines() {
+        let page_rect = Rect::from_hw(100, 200);
+
+        // Lines with no words or non-finite coordinates are invalid.
+        assert!(line_region(&[], page_rect).is_err());
+        let nan_rect = RotatedRect::new(
+            PointF::from_yx(f32::NAN, 10.),
+            Vec2::from_yx(1., 0.),
+            10.,
+            10.,
+        );
+        assert!(line_region(&[nan_rect], page_rect).is_err());
+
+        // Lines entirely outside the page have no region.
+        let outside = RotatedRect::from_rect(Rect::from_tlhw(500, 500, 20, 100).to_f32());
+        assert!(line_region(&[outside], page_rect).unwrap().is_none());
+
+        // Lines which extend outside the page are clipped to it.
+        let huge = RotatedRect::from_rect(Rect::from_tlhw(-1000, -1000, 2050, 1e6 as i32).to_f32());
+        let (_, line_rect) = line_region(&[huge], page_rect).unwrap().unwrap();
+        assert_eq!(line_rect, page_rect);
+    }
 }
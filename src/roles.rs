@@ -0,0 +1,427 @@
+//! Classification of recognized text by its role on the page, such as
+//! running headers, footers and page numbers.
+//!
+//! Lines are first grouped into blocks of adjacent lines. Each block is then
+//! classified using its position on the page, its content and, when several
+//! pages are classified together, whether similar text is repeated in the
+//! same place on other pages.
+
+use std::collections::{HashMap, HashSet};
+
+use rten_imageproc::Rect;
+
+use crate::text_items::{TextItem, TextLine};
+
+/// Fraction of the page height at the top and bottom of the page in which
+/// blocks are headers or footers, based on position alone.
+const MARGIN_FRACTION: f32 = 0.1;
+
+/// Fraction of the page height at the top and bottom of the page in which
+/// blocks are headers or footers if they are repeated on other pages.
+const REPEATED_MARGIN_FRACTION: f32 = 0.2;
+
+/// Fraction of the page width at the left and right of the page in which
+/// blocks are marginalia.
+const SIDE_MARGIN_FRACTION: f32 = 0.15;
+
+/// Maximum number of lines in a header or footer block.
+const MAX_HEADER_LINES: usize = 2;
+
+/// Words which start a figure or table caption.
+const CAPTION_PREFIXES: &[&str] = &["Figure", "Fig.", "Table", "Plate", "图", "表"];
+
+/// The role of a piece of text on a page.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TextRole {
+    /// Main content of the page.
+    #[default]
+    Body,
+
+    /// Running header at the top of the page.
+    Header,
+
+    /// Running footer at the bottom of the page.
+    Footer,
+
+    /// Page number, at the top or bottom of the page.
+    PageNumber,
+
+    /// Caption of a figure or table.
+    Caption,
+
+    /// Notes in the left or right margin of the page.
+    Marginalia,
+}
+
+/// Recognized lines of a page, together with the page's size.
+pub(crate) struct PageLines<'a> {
+    pub height: usize,
+    pub width: usize,
+
+    /// Lines in reading order.
+    pub lines: &'a [Option<TextLine>],
+}
+
+/// A group of adjacent lines.
+struct Block {
+    /// Indices of lines in the page.
+    lines: Vec<usize>,
+    rect: Rect,
+    text: String,
+}
+
+/// Vertical position of a block on the page.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Zone {
+    Top,
+    Bottom,
+}
+
+/// Group the lines of a page into blocks.
+///
+/// A line is added to the current block if it overlaps the previous line
+/// horizontally, and the vertical gap between them is no more than the height
+/// of the taller line. Otherwise it starts a new block.
+fn group_blocks(lines: &[Option<TextLine>]) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut prev_rect: Option<Rect> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some(line) = line else {
+            continue;
+        };
+        let rect = line.bounding_rect();
+        let text = line.to_string();
+
+        let continues_block = prev_rect.is_some_and(|prev| {
+            let h_overlap = prev.right().min(rect.right()) - prev.left().max(rect.left());
+            let v_gap = rect.top() - prev.bottom();
+            h_overlap > 0 && v_gap <= prev.height().max(rect.height())
+        });
+        match blocks.last_mut() {
+            Some(block) if continues_block => {
+                block.lines.push(i);
+                block.rect = block.rect.union(rect);
+                block.text.push('\n');
+                block.text.push_str(&text);
+            }
+            _ => blocks.push(Block {
+                lines: vec![i],
+                rect,
+                text,
+            }),
+        }
+        prev_rect = Some(rect);
+    }
+
+    blocks
+}
+
+/// Return the zone of the page that a block lies in, if any, where the top
+/// and bottom zones are `fraction` of the page height.
+fn zone(rect: Rect, page_height: usize, fraction: f32) -> Option<Zone> {
+    let margin = page_height as f32 * fraction;
+    if rect.bottom() as f32 <= margin {
+        Some(Zone::Top)
+    } else if rect.top() as f32 >= page_height as f32 - margin {
+        Some(Zone::Bottom)
+    } else {
+        None
+    }
+}
+
+/// Normalize text for comparison across pages, so that running headers
+/// which include page or chapter numbers are considered equal.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .map(|c| if c.is_numeric() { '#' } else { c })
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Return true if `text` looks like a page number, such as "12", "- 12 -",
+/// "Page 12", "12 of 40", "xii" or "第 12 页".
+fn is_page_number(text: &str) -> bool {
+    // A lone capital "I" is much more likely to be the pronoun than page i.
+    if text.trim() == "I" {
+        return false;
+    }
+
+    let text = text.to_lowercase();
+    let trim = |s: &str| -> String {
+        s.trim_matches(|c: char| c.is_whitespace() || "-–—.()[]|".contains(c))
+            .to_string()
+    };
+    let mut text = trim(&text);
+    for prefix in ["page", "p.", "第"] {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            text = trim(rest);
+        }
+    }
+    if let Some(rest) = text.strip_suffix('页') {
+        text = trim(rest);
+    }
+
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if is_number(&text) || is_roman_numeral(&text) {
+        return true;
+    }
+    let parts: Vec<&str> = text
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .filter(|s| !s.is_empty() && *s != "of")
+        .collect();
+    parts.len() == 2 && parts.iter().all(|p| is_number(p))
+}
+
+/// Return true if `text` is a lowercase roman numeral from 1 to 99.
+///
+/// Larger numbers are rarely used for page numbers, and would match words
+/// such as "mix" or "dim".
+fn is_roman_numeral(text: &str) -> bool {
+    let rest = strip_roman_digit(text, 'x', 'l', 'c');
+    let rest = strip_roman_digit(rest, 'i', 'v', 'x');
+    !text.is_empty() && rest.is_empty()
+}
+
+/// Strip the longest prefix of `text` which is a roman numeral digit using
+/// the symbols for one, five and ten units.
+fn strip_roman_digit(text: &str, one: char, five: char, ten: char) -> &str {
+    for pair in [[one, ten], [one, five]] {
+        let mut chars = text.chars();
+        if chars.next() == Some(pair[0]) && chars.next() == Some(pair[1]) {
+            return chars.as_str();
+        }
+    }
+    let text = text.strip_prefix(five).unwrap_or(text);
+    let ones = text.chars().take(3).take_while(|c| *c == one).count();
+    &text[ones..]
+}
+
+/// Return true if `text` starts with a caption prefix such as "Figure",
+/// followed by a label such as "3" or "A.1".
+fn is_caption(text: &str) -> bool {
+    let text = text.trim_start();
+    CAPTION_PREFIXES.iter().any(|prefix| {
+        let Some(head) = text.get(..prefix.len()) else {
+            return false;
+        };
+        if !head.eq_ignore_ascii_case(prefix) {
+            return false;
+        }
+        let rest = &text[prefix.len()..];
+        let label = rest.trim_start();
+        (label.len() < rest.len() || label.starts_with(|c: char| c.is_numeric()))
+            && label.starts_with(|c: char| c.is_numeric() || c.is_uppercase())
+    })
+}
+
+/// Classify each line of each page by its role.
+///
+/// The result has one entry per page, with one role per line. Lines which
+/// are `None` are classified as [TextRole::Body].
+///
+/// Text near the top or bottom of a page is classified as a header or footer
+/// if it is close to the edge of the page, or if similar text appears in the
+/// same area of another page. Numbers are ignored when comparing text, so
+/// that "Chapter 2" and "Chapter 3" are considered similar.
+pub(crate) fn classify_lines(pages: &[PageLines]) -> Vec<Vec<TextRole>> {
+    let page_blocks: Vec<Vec<Block>> = pages.iter().map(|p| group_blocks(p.lines)).collect();
+
+    // Count the number of pages on which each piece of text near the top or
+    // bottom of the page appears.
+    let mut repeats: HashMap<(Zone, String), usize> = HashMap::new();
+    for (page, blocks) in pages.iter().zip(&page_blocks) {
+        let seen: HashSet<(Zone, String)> = blocks
+            .iter()
+            .filter_map(|block| {
+                let zone = zone(block.rect, page.height, REPEATED_MARGIN_FRACTION)?;
+                Some((zone, normalize(&block.text)))
+            })
+            .collect();
+        for key in seen {
+            *repeats.entry(key).or_default() += 1;
+        }
+    }
+
+    pages
+        .iter()
+        .zip(&page_blocks)
+        .map(|(page, blocks)| {
+            let mut roles = vec![TextRole::Body; page.lines.len()];
+            for block in blocks {
+                let role = classify_block(block, page, |zone, text| {
+                    repeats.get(&(zone, text)).copied().unwrap_or(0) >= 2
+                });
+                for &line in &block.lines {
+                    roles[line] = role;
+                }
+            }
+            roles
+        })
+        .collect()
+}
+
+/// Classify a block on a page. `is_repeated(zone, text)` returns true if the
+/// normalized text appears in the same zone of more than one page.
+fn classify_block(
+    block: &Block,
+    page: &PageLines,
+    is_repeated: impl Fn(Zone, String) -> bool,
+) -> TextRole {
+    if block.lines.len() <= MAX_HEADER_LINES {
+        let margin_zone = zone(block.rect, page.height, MARGIN_FRACTION);
+        let repeated_zone = zone(block.rect, page.height, REPEATED_MARGIN_FRACTION);
+
+        if block.lines.len() == 1 && repeated_zone.is_some() && is_page_number(&block.text) {
+            return TextRole::PageNumber;
+        }
+
+        let zone =
+            margin_zone.or(repeated_zone.filter(|z| is_repeated(*z, normalize(&block.text))));
+        match zone {
+            Some(Zone::Top) => return TextRole::Header,
+            Some(Zone::Bottom) => return TextRole::Footer,
+            None => {}
+        }
+    }
+
+    let side_margin = page.width as f32 * SIDE_MARGIN_FRACTION;
+    if block.rect.right() as f32 <= side_margin
+        || block.rect.left() as f32 >= page.width as f32 - side_margin
+    {
+        return TextRole::Marginalia;
+    }
+
+    if is_caption(&block.text) {
+        return TextRole::Caption;
+    }
+
+    TextRole::Body
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_imageproc::Rect;
+
+    use super::{classify_lines, is_page_number, PageLines, TextRole};
+    use crate::text_items::{TextChar, TextLine};
+
+    /// Create a line of text with its top-left corner at `(top, left)`.
+    fn gen_line(top: i32, left: i32, text: &str) -> Option<TextLine> {
+        let chars = text
+            .chars()
+            .enumerate()
+            .map(|(i, char)| TextChar {
+                char,
+                rect: Rect::from_tlhw(top, left + i as i32 * 10, 16, 10),
+            })
+            .collect();
+        TextLine::try_new(chars)
+    }
+
+    #[test]
+    fn test_is_page_number() {
+        for text in [
+            "12",
+            "- 12 -",
+            "Page 12",
+            "12 of 40",
+            "12/40",
+            "xii",
+            "xix",
+            "XLIV",
+            "i",
+            "第 12 页",
+        ] {
+            assert!(is_page_number(text), "{} should be a page number", text);
+        }
+        for text in [
+            "Chapter 12",
+            "12 apples",
+            "mixed",
+            "",
+            // Words made of roman numeral letters.
+            "mix",
+            "did",
+            "civil",
+            "ill",
+            "I",
+            "iiii",
+            "vv",
+        ] {
+            assert!(
+                !is_page_number(text),
+                "{} should not be a page number",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_classify_lines() {
+        let gen_page = |page: usize| {
+            vec![
+                gen_line(20, 100, "Annual Report"),
+                // Below the top margin, but repeated on every page.
+                gen_line(120, 100, &format!("Chapter {}", page + 1)),
+                gen_line(200, 100, "Body text which fills the page"),
+                gen_line(220, 100, "and continues on the next line"),
+                gen_line(260, 10, "Note"),
+                gen_line(400, 100, &format!("Figure {}: Revenue by year", page)),
+                gen_line(500, 100, &format!("Chapter {} summary", page + 1)),
+                None,
+                gen_line(960, 380, &format!("{}", page + 1)),
+            ]
+        };
+        let pages: Vec<_> = (0..3).map(gen_page).collect();
+        let page_lines: Vec<_> = pages
+            .iter()
+            .map(|lines| PageLines {
+                height: 1000,
+                width: 800,
+                lines,
+            })
+            .collect();
+
+        let roles = classify_lines(&page_lines);
+
+        let expected = [
+            TextRole::Header,
+            TextRole::Header,
+            TextRole::Body,
+            TextRole::Body,
+            TextRole::Marginalia,
+            TextRole::Caption,
+            // Repeated, but not near the top or bottom of the page.
+            TextRole::Body,
+            TextRole::Body,
+            TextRole::PageNumber,
+        ];
+        assert_eq!(roles, vec![expected.to_vec(); 3]);
+
+        // Text which is near the top, but not the edge, of a single page is
+        // not a header.
+        let roles = classify_lines(&page_lines[..1]);
+        assert_eq!(roles[0][1], TextRole::Body);
+
+        // Text which appears several times on one page, in both the top and
+        // bottom areas, is not repeated.
+        let lines = [
+            gen_line(120, 100, "Draft"),
+            gen_line(850, 100, "Draft"),
+            gen_line(150, 500, "Draft"),
+        ];
+        let page = PageLines {
+            height: 1000,
+            width: 800,
+            lines: &lines,
+        };
+        assert_eq!(classify_lines(&[page]), [[TextRole::Body; 3]]);
+    }
+}
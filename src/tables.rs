@@ -0,0 +1,497 @@
+//! Detection of tables in recognized text, and extraction of their cells.
+//!
+//! Tables are found in the output of text recognition by looking for runs of
+//! consecutive rows which contain several widely-spaced pieces of text. The
+//! columns of a table are found from vertical ruling lines if there are any,
+//! otherwise from the whitespace between pieces of text. Horizontal ruling
+//! lines are used to group text lines into rows when cells contain several
+//! lines of text.
+
+use std::fmt::Write;
+
+use rten_imageproc::{LineF, Rect};
+
+use crate::text_items::{TextChar, TextItem, TextLine};
+
+/// Minimum gap between words, as a multiple of the line height, for the
+/// words to be considered to be in different cells.
+const CELL_GAP_FACTOR: f32 = 1.5;
+
+/// A table found by [OcrEngine::find_tables](crate::OcrEngine::find_tables).
+#[derive(Clone, Debug)]
+pub struct Table {
+    /// Bounding rect of the table's grid.
+    pub rect: Rect,
+
+    /// Rows of the table, from top to bottom.
+    pub rows: Vec<TableRow>,
+}
+
+/// A row in a [Table].
+#[derive(Clone, Debug)]
+pub struct TableRow {
+    /// Cells in the row, from left to right. All rows in a table have the
+    /// same number of cells.
+    pub cells: Vec<TableCell>,
+}
+
+/// A cell in a [Table].
+#[derive(Clone, Debug)]
+pub struct TableCell {
+    /// Area of the table's grid occupied by this cell.
+    pub rect: Rect,
+
+    /// Text in the cell, in reading order. Empty cells have no lines.
+    pub lines: Vec<TextLine>,
+}
+
+impl TableCell {
+    /// Return the text of the cell, with lines separated by newlines.
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Table {
+    /// Return the number of columns in the table.
+    pub fn columns(&self) -> usize {
+        self.rows.first().map(|r| r.cells.len()).unwrap_or(0)
+    }
+
+    /// Return the text of each cell, as a matrix indexed by `[row][column]`.
+    pub fn cell_texts(&self) -> Vec<Vec<String>> {
+        self.rows
+            .iter()
+            .map(|row| row.cells.iter().map(|c| c.text()).collect())
+            .collect()
+    }
+
+    /// Format the table as CSV, following RFC 4180.
+    ///
+    /// Cells which contain commas, quotes or newlines are quoted.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in self.cell_texts() {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|text| {
+                    if text.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", text.replace('"', "\"\""))
+                    } else {
+                        text.clone()
+                    }
+                })
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    /// Format the table as a JSON array of rows, where each row is an array
+    /// of cell strings.
+    pub fn to_json(&self) -> String {
+        let rows: Vec<String> = self
+            .cell_texts()
+            .iter()
+            .map(|row| {
+                let cells: Vec<String> = row.iter().map(|text| json_string(text)).collect();
+                format!("[{}]", cells.join(","))
+            })
+            .collect();
+        format!("[{}]", rows.join(","))
+    }
+}
+
+/// Encode a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A piece of a text line which is separated from other text on the line by
+/// a wide gap, and is therefore a candidate for a table cell.
+#[derive(Clone)]
+struct Fragment {
+    line: TextLine,
+    rect: Rect,
+}
+
+/// Split a text line into fragments at gaps which are wider than
+/// [CELL_GAP_FACTOR] times the line height.
+fn split_line(line: &TextLine) -> Vec<Fragment> {
+    let min_gap = (line.bounding_rect().height() as f32 * CELL_GAP_FACTOR) as i32;
+
+    let mut fragments = Vec::new();
+    let mut current: Vec<TextChar> = Vec::new();
+    let mut flush = |chars: &mut Vec<TextChar>| {
+        // Trim spaces from the ends of the fragment.
+        while chars.last().is_some_and(|c| c.char == ' ') {
+            chars.pop();
+        }
+        let start = chars
+            .iter()
+            .position(|c| c.char != ' ')
+            .unwrap_or(chars.len());
+        if let Some(line) = TextLine::try_new(chars.split_off(start)) {
+            fragments.push(Fragment {
+                rect: line.bounding_rect(),
+                line,
+            });
+        }
+        chars.clear();
+    };
+
+    for c in line.chars() {
+        if c.char != ' ' {
+            let prev_right = current
+                .iter()
+                .rev()
+                .find(|c| c.char != ' ')
+                .map(|c| c.rect.right());
+            if prev_right.is_some_and(|right| c.rect.left() - right > min_gap) {
+                flush(&mut current);
+            }
+        }
+        current.push(c.clone());
+    }
+    flush(&mut current);
+
+    fragments
+}
+
+/// Return true if the vertical extents of `a` and `b` overlap by at least
+/// half of the height of the shorter one.
+fn same_row(a: Rect, b: Rect) -> bool {
+    let overlap = a.bottom().min(b.bottom()) - a.top().max(b.top());
+    overlap * 2 >= a.height().min(b.height())
+}
+
+/// Group fragments into rows of vertically-overlapping fragments. Rows are
+/// sorted from top to bottom and fragments in a row from left to right.
+fn group_rows(mut fragments: Vec<Fragment>) -> Vec<Vec<Fragment>> {
+    fragments.sort_by_key(|f| (f.rect.top(), f.rect.left()));
+
+    let mut rows: Vec<Vec<Fragment>> = Vec::new();
+    for fragment in fragments {
+        let row = rows
+            .iter_mut()
+            .rev()
+            .take(2)
+            .find(|row| row.iter().any(|f| same_row(f.rect, fragment.rect)));
+        match row {
+            Some(row) => row.push(fragment),
+            None => rows.push(vec![fragment]),
+        }
+    }
+    for row in rows.iter_mut() {
+        row.sort_by_key(|f| f.rect.left());
+    }
+    rows.sort_by_key(|row| row.iter().map(|f| f.rect.top()).min());
+    rows
+}
+
+/// Return the X ranges of the columns in a table containing `rows`.
+///
+/// If vertical ruling lines cross the table, the columns are the spaces
+/// between them. Otherwise columns are found by merging the horizontal
+/// extents of all fragments which overlap.
+fn find_columns(rows: &[Vec<Fragment>], table_rect: Rect, rulings: &[LineF]) -> Vec<(i32, i32)> {
+    let mut ruling_xs: Vec<i32> = rulings
+        .iter()
+        .filter(|l| l.start.x == l.end.x)
+        .filter(|l| {
+            // Line must be inside the table and span most of its height.
+            let x = l.start.x as i32;
+            let (top, bottom) = (l.start.y.min(l.end.y), l.start.y.max(l.end.y));
+            let covered = bottom.min(table_rect.bottom() as f32) - top.max(table_rect.top() as f32);
+            x > table_rect.left()
+                && x < table_rect.right()
+                && covered >= table_rect.height() as f32 * 0.5
+        })
+        .map(|l| l.start.x as i32)
+        .collect();
+
+    if !ruling_xs.is_empty() {
+        ruling_xs.sort();
+        ruling_xs.dedup();
+        let mut edges = vec![table_rect.left()];
+        edges.extend(ruling_xs);
+        edges.push(table_rect.right());
+        return edges
+            .windows(2)
+            .map(|w| (w[0], w[1]))
+            .filter(|(left, right)| {
+                // Drop columns which contain no text.
+                rows.iter().flatten().any(|f| {
+                    let center = f.rect.left() + f.rect.width() / 2;
+                    center >= *left && center < *right
+                })
+            })
+            .collect();
+    }
+
+    let mut extents: Vec<(i32, i32)> = rows
+        .iter()
+        .flatten()
+        .map(|f| (f.rect.left(), f.rect.right()))
+        .collect();
+    extents.sort();
+
+    let mut columns: Vec<(i32, i32)> = Vec::new();
+    for (left, right) in extents {
+        match columns.last_mut() {
+            Some(col) if left <= col.1 => col.1 = col.1.max(right),
+            _ => columns.push((left, right)),
+        }
+    }
+    columns
+}
+
+/// Build a table from consecutive rows of fragments, or return `None` if the
+/// rows do not form a grid with at least two columns.
+fn build_table(rows: &[Vec<Fragment>], rulings: &[LineF]) -> Option<Table> {
+    let table_rect = rows
+        .iter()
+        .flatten()
+        .map(|f| f.rect)
+        .reduce(|a, b| a.union(b))?;
+    let columns = find_columns(rows, table_rect, rulings);
+    if columns.len() < 2 {
+        return None;
+    }
+
+    // Group text rows into table rows, using horizontal ruling lines if there
+    // are any. Text rows which are not separated by a ruling line are part of
+    // the same table row.
+    let mut ruling_ys: Vec<i32> = rulings
+        .iter()
+        .filter(|l| l.is_horizontal())
+        .filter(|l| {
+            let (left, right) = (l.start.x.min(l.end.x), l.start.x.max(l.end.x));
+            let covered = right.min(table_rect.right() as f32) - left.max(table_rect.left() as f32);
+            covered >= table_rect.width() as f32 * 0.5
+        })
+        .map(|l| l.start.y as i32)
+        .collect();
+    ruling_ys.sort();
+
+    let mut table_rows: Vec<Vec<&Fragment>> = Vec::new();
+    let mut prev_band = None;
+    for row in rows {
+        let band = row_band(row, &ruling_ys);
+        match table_rows.last_mut() {
+            Some(last) if !ruling_ys.is_empty() && prev_band == Some(band) => {
+                last.extend(row.iter())
+            }
+            _ => table_rows.push(row.iter().collect()),
+        }
+        prev_band = Some(band);
+    }
+
+    let rows = table_rows
+        .into_iter()
+        .map(|fragments| {
+            let top = fragments.iter().map(|f| f.rect.top()).min().unwrap_or(0);
+            let bottom = fragments.iter().map(|f| f.rect.bottom()).max().unwrap_or(0);
+            let cells = columns
+                .iter()
+                .map(|&(left, right)| {
+                    let mut lines: Vec<&Fragment> = fragments
+                        .iter()
+                        .copied()
+                        .filter(|f| {
+                            let center = f.rect.left() + f.rect.width() / 2;
+                            center >= left && center < right.max(left + 1)
+                                || (f.rect.left() >= left && f.rect.right() <= right)
+                        })
+                        .collect();
+                    lines.sort_by_key(|f| (f.rect.top(), f.rect.left()));
+                    TableCell {
+                        rect: Rect::from_tlbr(top, left, bottom, right),
+                        lines: lines.into_iter().map(|f| f.line.clone()).collect(),
+                    }
+                })
+                .collect();
+            TableRow { cells }
+        })
+        .collect();
+
+    Some(Table {
+        rect: table_rect,
+        rows,
+    })
+}
+
+/// Find tables in recognized text lines.
+///
+/// `lines` are text lines in reading order, as returned by text recognition.
+/// `rulings` are horizontal and vertical ruling lines in the image.
+pub fn find_tables(lines: &[Option<TextLine>], rulings: &[LineF]) -> Vec<Table> {
+    let fragments: Vec<Fragment> = lines.iter().flatten().flat_map(split_line).collect();
+    let rows = group_rows(fragments);
+
+    let mut ruling_ys: Vec<i32> = rulings
+        .iter()
+        .filter(|l| l.is_horizontal())
+        .map(|l| l.start.y as i32)
+        .collect();
+    ruling_ys.sort();
+
+    // A table is a run of at least two consecutive rows which each contain
+    // multiple fragments. Rows with a single fragment continue a run if they
+    // are enclosed by the same pair of ruling lines as the previous row, as
+    // they are then additional lines of text in that row's cells.
+    let mut tables = Vec::new();
+    let mut start = 0;
+    while start < rows.len() {
+        let mut end = start;
+        while end < rows.len() {
+            let continues_cell = end > start && {
+                let band = row_band(&rows[end], &ruling_ys);
+                band > 0 && band < ruling_ys.len() && band == row_band(&rows[end - 1], &ruling_ys)
+            };
+            if rows[end].len() < 2 && !continues_cell {
+                break;
+            }
+            end += 1;
+        }
+        if end - start >= 2 {
+            tables.extend(build_table(&rows[start..end], rulings));
+        }
+        start = end + 1;
+    }
+    tables
+}
+
+/// Return the number of horizontal ruling lines, with Y coordinates
+/// `ruling_ys`, which are above a row.
+fn row_band(row: &[Fragment], ruling_ys: &[i32]) -> usize {
+    let top = row.iter().map(|f| f.rect.top()).min().unwrap_or(0);
+    ruling_ys.iter().filter(|&&y| y <= top).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use rten_imageproc::{LineF, PointF, Rect};
+
+    use super::find_tables;
+    use crate::text_items::{TextChar, TextLine};
+
+    const CHAR_WIDTH: i32 = 8;
+    const CHAR_HEIGHT: i32 = 12;
+
+    /// Create a line containing `cells` at the given X positions.
+    fn gen_line(top: i32, cells: &[(i32, &str)]) -> Option<TextLine> {
+        let mut chars = Vec::new();
+        for (i, (left, text)) in cells.iter().enumerate() {
+            if i > 0 {
+                chars.push(TextChar {
+                    char: ' ',
+                    rect: Rect::from_tlhw(top, left - CHAR_WIDTH, CHAR_HEIGHT, CHAR_WIDTH),
+                });
+            }
+            for (j, char) in text.chars().enumerate() {
+                let left = left + j as i32 * CHAR_WIDTH;
+                chars.push(TextChar {
+                    char,
+                    rect: Rect::from_tlhw(top, left, CHAR_HEIGHT, CHAR_WIDTH),
+                });
+            }
+        }
+        TextLine::try_new(chars)
+    }
+
+    #[test]
+    fn test_find_tables_whitespace() {
+        let lines = [
+            gen_line(0, &[(0, "Invoice 1234")]),
+            gen_line(30, &[(0, "Item"), (200, "Qty"), (300, "Price")]),
+            gen_line(50, &[(0, "Widget, large"), (200, "2"), (300, "9.99")]),
+            gen_line(70, &[(0, "Gadget"), (200, "10"), (300, "\"1.50\"")]),
+            gen_line(100, &[(0, "Total 29.99")]),
+        ];
+
+        let tables = find_tables(&lines, &[]);
+
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0];
+        assert_eq!(table.columns(), 3);
+        assert_eq!(
+            table.cell_texts(),
+            [
+                ["Item", "Qty", "Price"],
+                ["Widget, large", "2", "9.99"],
+                ["Gadget", "10", "\"1.50\""],
+            ]
+        );
+        assert_eq!(
+            table.to_csv(),
+            "Item,Qty,Price\r\n\"Widget, large\",2,9.99\r\nGadget,10,\"\"\"1.50\"\"\"\r\n"
+        );
+        assert_eq!(
+            table.to_json(),
+            r#"[["Item","Qty","Price"],["Widget, large","2","9.99"],["Gadget","10","\"1.50\""]]"#
+        );
+    }
+
+    #[test]
+    fn test_find_tables_ruling_lines() {
+        // Two-column ruled table, where the first cell of the second row
+        // contains two lines of text. The second of these has only one
+        // fragment, so it is only part of the table because it is between
+        // the same horizontal rules as the line above. The column boundary is
+        // taken from the vertical rule.
+        let lines = [
+            gen_line(5, &[(10, "Name"), (160, "Value")]),
+            gen_line(35, &[(10, "First"), (160, "1")]),
+            gen_line(50, &[(10, "continued")]),
+            gen_line(75, &[(10, "Second"), (160, "2")]),
+        ];
+        let hline =
+            |y: f32| LineF::from_endpoints(PointF::from_yx(y, 0.), PointF::from_yx(y, 300.));
+        let vline =
+            |x: f32| LineF::from_endpoints(PointF::from_yx(0., x), PointF::from_yx(100., x));
+        let rulings = [hline(0.), hline(25.), hline(70.), hline(95.), vline(150.)];
+
+        let tables = find_tables(&lines, &rulings);
+
+        assert_eq!(tables.len(), 1);
+        assert_eq!(
+            tables[0].cell_texts(),
+            [
+                ["Name", "Value"],
+                ["First\ncontinued", "1"],
+                ["Second", "2"],
+            ]
+        );
+        assert_eq!(
+            tables[0].to_csv(),
+            "Name,Value\r\n\"First\ncontinued\",1\r\nSecond,2\r\n"
+        );
+
+        // Without ruling lines, the single-fragment line ends the table.
+        let tables = find_tables(&lines, &[]);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].cell_texts(), [["Name", "Value"], ["First", "1"]]);
+    }
+}
@@ -1,5 +1,8 @@
 use rten_imageproc::{Point, Rect};
 
+mod synthetic;
+pub use synthetic::{PageBuilder, SyntheticPage};
+
 /// Generate a grid of uniformly sized and spaced rects.
 ///
 /// `grid_shape` is a (rows, columns) tuple. `rect_size` and `gap_size` are
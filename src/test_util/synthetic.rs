@@ -0,0 +1,220 @@
+//! Generation of synthetic document pages with known word positions and
+//! reading order, for testing layout analysis on realistic geometry.
+
+use rten_imageproc::{draw_line, Line, LineF, Point, PointF, Polygon, Rect, RotatedRect, Vec2};
+use rten_tensor::NdTensor;
+
+/// A generated page.
+pub struct SyntheticPage {
+    pub height: i32,
+    pub width: i32,
+
+    /// Oriented boxes of all words on the page.
+    pub words: Vec<RotatedRect>,
+
+    /// Lines in reading order. Each line lists indices into `words`, from
+    /// left to right.
+    pub lines: Vec<Vec<usize>>,
+
+    /// Printed horizontal and vertical lines, such as column rules.
+    pub rulings: Vec<LineF>,
+}
+
+impl SyntheticPage {
+    /// Return the word boxes of each line, in reading order.
+    pub fn expected_lines(&self) -> Vec<Vec<RotatedRect>> {
+        self.lines
+            .iter()
+            .map(|line| line.iter().map(|&i| self.words[i]).collect())
+            .collect()
+    }
+
+    /// Convert lines of word boxes, such as the output of layout analysis,
+    /// to lines of indices into [SyntheticPage::words].
+    ///
+    /// Boxes which are not words on this page are mapped to `None`.
+    pub fn line_indices(&self, lines: &[Vec<RotatedRect>]) -> Vec<Vec<Option<usize>>> {
+        lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|word| {
+                        self.words
+                            .iter()
+                            .position(|w| w.corners() == word.corners())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Render the page as a greyscale CHW image with white words on a black
+    /// background, matching the input expected by the fake detection model
+    /// used in tests.
+    pub fn render(&self) -> NdTensor<f32, 3> {
+        let mut image = NdTensor::zeros([1, self.height as usize, self.width as usize]);
+        for word in &self.words {
+            let corners = word
+                .corners()
+                .map(|c| Point::from_yx(c.y.round() as i32, c.x.round() as i32));
+            for p in Polygon::new(corners).fill_iter() {
+                if p.y >= 0 && p.x >= 0 && p.y < self.height && p.x < self.width {
+                    image[[0, p.y as usize, p.x as usize]] = 1.;
+                }
+            }
+        }
+        for ruling in &self.rulings {
+            let line = Line::from_endpoints(
+                Point::from_yx(ruling.start.y as i32, ruling.start.x as i32),
+                Point::from_yx(ruling.end.y as i32, ruling.end.x as i32),
+            );
+            draw_line(image.slice_mut(0), line, 1., 1);
+        }
+        image
+    }
+}
+
+/// Builds a [SyntheticPage] from blocks of text.
+///
+/// Blocks are added in reading order. Word widths are chosen randomly, using
+/// a fixed seed so that generated pages are deterministic.
+pub struct PageBuilder {
+    height: i32,
+    width: i32,
+    words: Vec<RotatedRect>,
+    lines: Vec<Vec<usize>>,
+    rulings: Vec<LineF>,
+    rng: fastrand::Rng,
+}
+
+impl PageBuilder {
+    pub fn new(height: i32, width: i32, seed: u64) -> PageBuilder {
+        PageBuilder {
+            height,
+            width,
+            words: Vec::new(),
+            lines: Vec::new(),
+            rulings: Vec::new(),
+            rng: fastrand::Rng::with_seed(seed),
+        }
+    }
+
+    /// Fill `rect` with lines of words of height `word_height`.
+    ///
+    /// Word and line spacing are proportional to the word height, as in
+    /// typical printed text. Use different word heights to create headings
+    /// and body text of mixed sizes.
+    pub fn paragraph(&mut self, rect: Rect, word_height: i32) -> &mut Self {
+        self.rotated_paragraph(rect, word_height, 0.)
+    }
+
+    /// Fill `rect` with lines of words, and then rotate the whole block by
+    /// `angle` degrees clockwise around its center.
+    pub fn rotated_paragraph(&mut self, rect: Rect, word_height: i32, angle: f32) -> &mut Self {
+        let word_gap = (word_height / 2).max(1);
+        let line_gap = (word_height / 2).max(1);
+        let center = rect.to_f32().center();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let rotate = |p: PointF| {
+            let (dy, dx) = (p.y - center.y, p.x - center.x);
+            PointF::from_yx(
+                center.y + dx * sin + dy * cos,
+                center.x + dx * cos - dy * sin,
+            )
+        };
+        let up_axis = Vec2::from_yx(-cos, sin);
+
+        let mut top = rect.top();
+        while top + word_height <= rect.bottom() {
+            let mut line = Vec::new();
+            let mut left = rect.left();
+            loop {
+                let word_width = self.rng.i32(word_height..word_height * 4);
+                if left + word_width > rect.right() {
+                    break;
+                }
+                let word_center = PointF::from_yx(
+                    top as f32 + word_height as f32 / 2.,
+                    left as f32 + word_width as f32 / 2.,
+                );
+                line.push(self.words.len());
+                self.words.push(RotatedRect::new(
+                    rotate(word_center),
+                    up_axis,
+                    word_width as f32,
+                    word_height as f32,
+                ));
+                left += word_width + word_gap;
+            }
+            if !line.is_empty() {
+                self.lines.push(line);
+            }
+            top += word_height + line_gap;
+        }
+        self
+    }
+
+    /// Add `columns` columns of paragraphs which fill `rect`, separated by
+    /// gaps of `gap` pixels. Columns are read from left to right.
+    pub fn columns(&mut self, rect: Rect, columns: i32, gap: i32, word_height: i32) -> &mut Self {
+        let col_width = (rect.width() - gap * (columns - 1)) / columns;
+        for col in 0..columns {
+            let left = rect.left() + col * (col_width + gap);
+            let col_rect = Rect::from_tlhw(rect.top(), left, rect.height(), col_width);
+            self.paragraph(col_rect, word_height);
+        }
+        self
+    }
+
+    /// Add a table with one word per cell, with its top-left corner at
+    /// `top_left`. `cell_size` is a `(height, width)` tuple.
+    ///
+    /// Each row of the table is one line in reading order, as cells are read
+    /// from left to right.
+    pub fn table(
+        &mut self,
+        top_left: Point,
+        rows: i32,
+        cols: i32,
+        cell_size: (i32, i32),
+        word_height: i32,
+    ) -> &mut Self {
+        let (cell_h, cell_w) = cell_size;
+        for row in 0..rows {
+            let mut line = Vec::new();
+            for col in 0..cols {
+                let word_width = self
+                    .rng
+                    .i32(word_height..(cell_w * 3 / 4).max(word_height + 1));
+                let word = Rect::from_tlhw(
+                    top_left.y + row * cell_h + (cell_h - word_height) / 2,
+                    top_left.x + col * cell_w + (cell_w - word_width) / 2,
+                    word_height,
+                    word_width,
+                );
+                line.push(self.words.len());
+                self.words.push(RotatedRect::from_rect(word.to_f32()));
+            }
+            self.lines.push(line);
+        }
+        self
+    }
+
+    /// Add a printed line from `start` to `end`, which must be either
+    /// horizontal or vertical.
+    pub fn ruling(&mut self, start: Point, end: Point) -> &mut Self {
+        self.rulings
+            .push(LineF::from_endpoints(start.to_f32(), end.to_f32()));
+        self
+    }
+
+    pub fn build(&mut self) -> SyntheticPage {
+        SyntheticPage {
+            height: self.height,
+            width: self.width,
+            words: std::mem::take(&mut self.words),
+            lines: std::mem::take(&mut self.lines),
+            rulings: std::mem::take(&mut self.rulings),
+        }
+    }
+}
@@ -44,7 +44,7 @@ impl fmt::Display for TextLine {
 }
 
 /// Details of a single character that was recognized.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct TextChar {
     /// Character that was recognized.
     pub char: char,
@@ -57,7 +57,7 @@ pub struct TextChar {
 ///
 /// This includes the sequence of characters that were found and associated
 /// metadata (eg. bounding boxes).
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct TextLine {
     chars: Vec<TextChar>,
 }
@@ -67,11 +67,22 @@ impl TextLine {
     ///
     /// Word boundaries are inferred from the presence of characters with
     /// [TextChar::char] values that are ASCII spaces.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chars` is empty. Use [TextLine::try_new] to handle empty
+    /// lines without panicking.
     pub fn new(chars: Vec<TextChar>) -> TextLine {
         assert!(!chars.is_empty(), "Text lines must not be empty");
         TextLine { chars }
     }
 
+    /// Create a new text line which contains the given characters, or return
+    /// `None` if `chars` is empty.
+    pub fn try_new(chars: Vec<TextChar>) -> Option<TextLine> {
+        (!chars.is_empty()).then_some(TextLine { chars })
+    }
+
     /// Return an iterator over words in this line.
     pub fn words(&self) -> impl Iterator<Item = TextWord> {
         self.chars()
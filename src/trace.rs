@@ -0,0 +1,301 @@
+//! Structured timing events for stages of the OCR pipeline.
+//!
+//! Each stage of the pipeline (preprocessing, detection, layout analysis and
+//! recognition) emits a [TraceEvent] when it completes, with the time taken
+//! and counts of the items it processed. Events are delivered to the
+//! [TraceSink] configured via
+//! [OcrEngineParams::trace_sink](crate::OcrEngineParams::trace_sink).
+//!
+//! Events can be aggregated into an [OcrStats] summary, which is also
+//! returned by methods such as
+//! [OcrEngine::get_text_with_stats](crate::OcrEngine::get_text_with_stats).
+
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Stage of the OCR pipeline which a [TraceEvent] describes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TraceStage {
+    /// Conversion of an input image into the engine's internal format.
+    Preprocess,
+
+    /// Detection of text words in an image.
+    Detection,
+
+    /// Grouping of words into lines in reading order.
+    Layout,
+
+    /// Recognition of all text lines passed to a recognition call.
+    Recognition,
+
+    /// Recognition of one batch of similarly-sized text lines.
+    RecognitionBatch,
+}
+
+impl TraceStage {
+    /// Return the name of this stage as used in log output.
+    pub fn name(self) -> &'static str {
+        match self {
+            TraceStage::Preprocess => "preprocess",
+            TraceStage::Detection => "detection",
+            TraceStage::Layout => "layout",
+            TraceStage::Recognition => "recognition",
+            TraceStage::RecognitionBatch => "recognition_batch",
+        }
+    }
+}
+
+/// Record of a completed pipeline stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    /// Stage which completed.
+    pub stage: TraceStage,
+
+    /// Wall-clock time taken by the stage.
+    pub duration: Duration,
+
+    /// Named counts associated with the stage, such as the number of words
+    /// found by detection.
+    pub fields: Vec<(&'static str, usize)>,
+}
+
+impl TraceEvent {
+    /// Return the value of the field named `name`.
+    pub fn field(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:.2}ms",
+            self.stage.name(),
+            self.duration.as_secs_f64() * 1000.
+        )?;
+        for (name, value) in &self.fields {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Receiver for [TraceEvent]s.
+///
+/// Events from recognition batches are emitted from worker threads, so sinks
+/// must be thread-safe.
+pub trait TraceSink: Send + Sync {
+    /// Handle an event.
+    fn event(&self, event: &TraceEvent);
+}
+
+/// Sink which writes events to stdout, or to the browser console in
+/// WebAssembly.
+///
+/// This is the sink used if [OcrEngineParams::debug](crate::OcrEngineParams::debug)
+/// is enabled and no other sink is configured.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LogTraceSink;
+
+impl TraceSink for LogTraceSink {
+    fn event(&self, event: &TraceEvent) {
+        crate::log!("{}", event);
+    }
+}
+
+/// Summary of the work done by the OCR pipeline, aggregated from
+/// [TraceEvent]s.
+///
+/// Times are summed over all events for a stage. When several images are
+/// processed in parallel, for example by
+/// [OcrEngine::process_batch_with_stats](crate::OcrEngine::process_batch_with_stats),
+/// the total can therefore exceed the elapsed wall-clock time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OcrStats {
+    /// Time spent preparing input images.
+    ///
+    /// Inputs are prepared before they are passed to methods such as
+    /// [OcrEngine::get_text_with_stats](crate::OcrEngine::get_text_with_stats),
+    /// so the time is recorded when each input is created and added to the
+    /// statistics of the methods it is passed to.
+    pub preprocess_time: Duration,
+
+    /// Time spent detecting words.
+    pub detection_time: Duration,
+
+    /// Time spent grouping words into lines.
+    pub layout_time: Duration,
+
+    /// Time spent recognizing text lines, including all batches.
+    pub recognition_time: Duration,
+
+    /// Number of words found by detection.
+    pub words: usize,
+
+    /// Number of text lines found by layout analysis.
+    pub lines: usize,
+
+    /// Number of batches which were passed to the recognition model.
+    pub recognition_batches: usize,
+
+    /// Total width of all line images in recognition batches, including
+    /// padding.
+    pub recognition_columns: usize,
+
+    /// Width of recognition batch inputs which consisted of padding, added so
+    /// that lines in a batch have equal width.
+    pub padding_columns: usize,
+}
+
+impl OcrStats {
+    /// Update statistics with the information from an event.
+    pub fn record(&mut self, event: &TraceEvent) {
+        let field = |name| event.field(name).unwrap_or(0);
+        match event.stage {
+            TraceStage::Preprocess => self.preprocess_time += event.duration,
+            TraceStage::Detection => {
+                self.detection_time += event.duration;
+                self.words += field("words");
+            }
+            TraceStage::Layout => {
+                self.layout_time += event.duration;
+                self.lines += field("lines");
+            }
+            TraceStage::Recognition => self.recognition_time += event.duration,
+            TraceStage::RecognitionBatch => {
+                self.recognition_batches += 1;
+                self.recognition_columns += field("lines") * field("width");
+                self.padding_columns += field("padding");
+            }
+        }
+    }
+
+    /// Return the fraction of recognition batch inputs which consisted of
+    /// padding, or zero if no batches were processed.
+    pub fn padding_fraction(&self) -> f32 {
+        if self.recognition_columns == 0 {
+            return 0.;
+        }
+        self.padding_columns as f32 / self.recognition_columns as f32
+    }
+}
+
+/// Sink which accumulates [OcrStats] and forwards events to another sink.
+pub(crate) struct StatsCollector<'a> {
+    stats: Mutex<OcrStats>,
+    next: Option<&'a dyn TraceSink>,
+}
+
+impl<'a> StatsCollector<'a> {
+    pub fn new(next: Option<&'a dyn TraceSink>) -> StatsCollector<'a> {
+        StatsCollector {
+            stats: Mutex::new(OcrStats::default()),
+            next,
+        }
+    }
+
+    pub fn into_stats(self) -> OcrStats {
+        self.stats
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl TraceSink for StatsCollector<'_> {
+    fn event(&self, event: &TraceEvent) {
+        self.stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(event);
+        if let Some(next) = self.next {
+            next.event(event);
+        }
+    }
+}
+
+/// Measures the duration of a pipeline stage and reports it to a sink when
+/// dropped.
+///
+/// The event is reported even if the stage fails, so that the durations of
+/// failed stages are visible.
+pub(crate) struct Span<'a> {
+    sink: Option<&'a dyn TraceSink>,
+    stage: TraceStage,
+    start: Timer,
+    fields: Vec<(&'static str, usize)>,
+}
+
+impl<'a> Span<'a> {
+    /// Start timing a stage. If `sink` is `None` no event is emitted.
+    pub fn enter(sink: Option<&'a dyn TraceSink>, stage: TraceStage) -> Span<'a> {
+        Span {
+            sink,
+            stage,
+            start: Timer::start(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Return the time elapsed since the stage started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Set a named count which is included in the stage's event.
+    pub fn record(&mut self, name: &'static str, value: usize) {
+        if self.sink.is_some() {
+            self.fields.push((name, value));
+        }
+    }
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        if let Some(sink) = self.sink {
+            sink.event(&TraceEvent {
+                stage: self.stage,
+                duration: self.start.elapsed(),
+                fields: std::mem::take(&mut self.fields),
+            });
+        }
+    }
+}
+
+/// Monotonic timer.
+///
+/// `std::time::Instant` is not supported in WebAssembly without WASI, so the
+/// JS `performance.now` API is used there instead.
+#[cfg(not(target_arch = "wasm32"))]
+struct Timer(std::time::Instant);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Timer {
+    fn start() -> Timer {
+        Timer(std::time::Instant::now())
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+struct Timer(f64);
+
+#[cfg(target_arch = "wasm32")]
+impl Timer {
+    fn start() -> Timer {
+        Timer(crate::log::js_performance_now())
+    }
+
+    fn elapsed(&self) -> Duration {
+        let elapsed_ms = (crate::log::js_performance_now() - self.0).max(0.);
+        Duration::from_secs_f64(elapsed_ms / 1000.)
+    }
+}
@@ -3,7 +3,12 @@ use wasm_bindgen::prelude::*;
 use rten::ops;
 use rten::{Model, ModelOptions, OpRegistry};
 
-use crate::{ImageSource, OcrEngine as BaseOcrEngine, OcrEngineParams, OcrInput, TextItem};
+use std::sync::Arc;
+
+use crate::{
+    DebugRenderOptions, ImageSource, LogTraceSink, OcrEngine as BaseOcrEngine, OcrEngineParams,
+    OcrInput, TextItem,
+};
 use rten_imageproc::{min_area_rect, BoundingRect, PointF};
 use rten_tensor::prelude::*;
 use web_sys::console;
@@ -13,6 +18,7 @@ use web_sys::console;
 pub struct OcrEngineInit {
     detection_model: Option<Model>,
     recognition_model: Option<Model>,
+    tracing: bool,
 }
 
 impl Default for OcrEngineInit {
@@ -28,6 +34,7 @@ impl OcrEngineInit {
         OcrEngineInit {
             detection_model: None,
             recognition_model: None,
+            tracing: false,
         }
     }
 
@@ -78,6 +85,12 @@ impl OcrEngineInit {
         self.recognition_model = Some(model);
         Ok(())
     }
+
+    /// 启用或禁用将各处理阶段的耗时和计数输出到浏览器控制台。
+    #[wasm_bindgen(js_name = setTracing)]
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.tracing = enabled;
+    }
 }
 
 /// OcrEngine 是用于在 WebAssembly 中执行 OCR 的主要 API。
@@ -97,10 +110,12 @@ impl OcrEngine {
         let OcrEngineInit {
             detection_model,
             recognition_model,
+            tracing,
         } = init;
         let engine = BaseOcrEngine::new(OcrEngineParams {
             detection_model,
             recognition_model,
+            trace_sink: tracing.then(|| Arc::new(LogTraceSink) as _),
             ..Default::default()
         })
         .map_err(|e| e.to_string())?;
@@ -134,6 +149,7 @@ impl OcrEngine {
         Ok(self
             .engine
             .find_text_lines(&image.input, &words)
+            .map_err(|e| e.to_string())?
             .into_iter()
             .map(|words| {
                 DetectedLine::new(
@@ -187,6 +203,17 @@ impl OcrEngine {
             .map_err(|e| e.to_string())
     }
 
+    /// 将文本检测和布局分析的结果绘制到图像的副本上，用于调试。
+    ///
+    /// 返回与输入图像大小相同的按行主序排列的 RGBA 像素数据，可用于构造 `ImageData`。
+    #[wasm_bindgen(js_name = renderDebug)]
+    pub fn render_debug(&self, image: &Image) -> Result<Vec<u8>, String> {
+        self.engine
+            .render_debug(&image.input, &DebugRenderOptions::default())
+            .map(|debug_image| debug_image.into_data())
+            .map_err(|e| e.to_string())
+    }
+
     /// 在图像中检测和识别文本。
     ///
     /// 返回可以用于查询文本和每个行的边界框的 `TextLine` 对象列表。
@@ -196,7 +223,10 @@ impl OcrEngine {
             .engine
             .detect_words(&image.input)
             .map_err(|e| e.to_string())?;
-        let lines = self.engine.find_text_lines(&image.input, &words);
+        let lines = self
+            .engine
+            .find_text_lines(&image.input, &words)
+            .map_err(|e| e.to_string())?;
         let text_lines = self
             .engine
             .recognize_text(&image.input, &lines)
@@ -225,7 +255,10 @@ impl OcrEngine {
         // console::log_2(&"words".into(), &format!("{:?}", words).into());
 
         // 找到图像中的文本行
-        let lines = self.engine.find_text_lines(&image.input, &words);
+        let lines = self
+            .engine
+            .find_text_lines(&image.input, &words)
+            .map_err(|e| e.to_string())?;
         // console::log_2(&"lines".into(), &format!("{:?}", lines).into());
         // 识别图像中的文本行
         // 识别的结果是一个 TextLine 列表